@@ -1,7 +1,11 @@
+use crate::env::get_home_dir;
+use crate::error::Error;
 use clap::ValueEnum;
 use clap_complete::Shell;
 use clap_complete_nushell::Nushell;
 use std::fmt;
+use std::fs::{File, create_dir_all};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 #[derive(Debug, Clone)]
@@ -21,6 +25,72 @@ impl CompletionShell {
             }
         }
     }
+
+    /// Returns the conventional completions file name for the shell.
+    pub fn file_name(&self, bin_name: &str) -> String {
+        match self {
+            CompletionShell::Clap(Shell::Bash) => bin_name.to_string(),
+            CompletionShell::Clap(Shell::Zsh) => format!("_{bin_name}"),
+            CompletionShell::Clap(Shell::Fish) => format!("{bin_name}.fish"),
+            CompletionShell::Clap(Shell::PowerShell) => format!("_{bin_name}.ps1"),
+            CompletionShell::Clap(Shell::Elvish) => format!("{bin_name}.elv"),
+            CompletionShell::Clap(_) => bin_name.to_string(),
+            CompletionShell::Nushell => format!("{bin_name}.nu"),
+        }
+    }
+
+    /// Returns the standard completions directory of the shell for the current user.
+    pub fn default_directory(&self) -> Result<PathBuf, Error> {
+        let home_dir = get_home_dir()?;
+        match self {
+            CompletionShell::Clap(Shell::Bash) => Ok(home_dir
+                .join(".local")
+                .join("share")
+                .join("bash-completion")
+                .join("completions")),
+            CompletionShell::Clap(Shell::Zsh) => Ok(home_dir.join(".zfunc")),
+            CompletionShell::Clap(Shell::Fish) => {
+                Ok(home_dir.join(".config").join("fish").join("completions"))
+            }
+            CompletionShell::Nushell => {
+                Ok(home_dir.join(".config").join("nushell").join("completions"))
+            }
+            _ => Err(Error::UnsupportedCompletionsDirectory(self.to_string())),
+        }
+    }
+
+    /// Returns how to make the shell load the completions files of the given directory, if it
+    /// doesn't load them on its own.
+    pub fn setup_hint(&self, directory: &Path, bin_name: &str) -> Option<String> {
+        match self {
+            CompletionShell::Clap(Shell::Zsh) => Some(format!(
+                "Add '{}' to the 'fpath' of '~/.zshrc', before 'compinit' runs",
+                directory.display()
+            )),
+            CompletionShell::Nushell => Some(format!(
+                "Add 'source {}' to the 'config.nu' of Nushell",
+                directory.join(self.file_name(bin_name)).display()
+            )),
+            _ => None,
+        }
+    }
+
+    /// Writes the completions file into the given directory, returning its path.
+    pub fn generate_to(
+        &self,
+        cmd: &mut clap::Command,
+        bin_name: &str,
+        directory: &Path,
+    ) -> Result<PathBuf, Error> {
+        if !directory.exists() {
+            create_dir_all(directory)
+                .map_err(|_| Error::CreateDirectory(directory.display().to_string()))?;
+        }
+        let path = directory.join(self.file_name(bin_name));
+        let mut file = File::create(&path)?;
+        self.generate(cmd, bin_name, &mut file);
+        Ok(path)
+    }
 }
 
 impl ValueEnum for CompletionShell {
@@ -1,14 +1,21 @@
 //! Environment variables set up and export file support.
 
-use crate::error::Error;
+use crate::{error::Error, targets::Target, toolchain::settings};
+use clap::ValueEnum;
 use directories::BaseDirs;
 use log::debug;
+use serde_json::{Value, json};
+#[cfg(unix)]
+use std::process::Command;
 use std::{
     env,
-    fs::File,
+    ffi::OsString,
+    fs::{File, OpenOptions, copy, create_dir_all, read_to_string, write},
     io::Write,
+    iter,
     path::{Path, PathBuf},
 };
+use strum::{Display, EnumIter, IntoEnumIterator};
 #[cfg(windows)]
 use winreg::{
     RegKey,
@@ -16,9 +23,235 @@ use winreg::{
 };
 
 #[cfg(windows)]
-const DEFAULT_EXPORT_FILE: &str = "export-esp.ps1";
+pub const DEFAULT_EXPORT_FILE: &str = "export-esp.ps1";
 #[cfg(not(windows))]
-const DEFAULT_EXPORT_FILE: &str = "export-esp.sh";
+pub const DEFAULT_EXPORT_FILE: &str = "export-esp.sh";
+
+/// Comment preceding the exports common to every target.
+const COMMON_EXPORTS_COMMENT: &str = "# Common to all targets";
+/// Comment prefix preceding the exports specific to some targets.
+const TARGET_EXPORTS_COMMENT: &str = "# Targets: ";
+/// Comment ending the lines espup adds to shell profiles, so they can be removed on uninstall.
+const PROFILE_COMMENT: &str = "# Added by espup";
+
+#[cfg(windows)]
+pub(crate) const PATH_SEPARATOR: &str = ";";
+#[cfg(not(windows))]
+pub(crate) const PATH_SEPARATOR: &str = ":";
+
+/// Shells that export files can be generated for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum ExportShell {
+    /// Bash, Zsh and other POSIX shells.
+    Posix,
+    /// Fish shell.
+    Fish,
+    /// PowerShell.
+    Powershell,
+}
+
+impl ExportShell {
+    /// Returns the extension of the export files for the shell.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportShell::Posix => "sh",
+            ExportShell::Fish => "fish",
+            ExportShell::Powershell => "ps1",
+        }
+    }
+
+    /// Formats the export of an environment variable, `PATH` values are prepended to the current one.
+    pub fn format_export(&self, name: &str, value: &str) -> String {
+        match (self, name) {
+            (ExportShell::Posix, "PATH") => {
+                format!("export PATH=\"{value}{PATH_SEPARATOR}$PATH\"")
+            }
+            (ExportShell::Posix, _) => format!("export {name}=\"{value}\""),
+            (ExportShell::Fish, "PATH") => format!("set -gx PATH \"{value}\" $PATH"),
+            (ExportShell::Fish, _) => format!("set -gx {name} \"{value}\""),
+            (ExportShell::Powershell, "PATH") => {
+                format!("$Env:PATH = \"{value}{PATH_SEPARATOR}\" + $Env:PATH")
+            }
+            (ExportShell::Powershell, _) => format!("$Env:{name} = \"{value}\""),
+        }
+    }
+}
+
+/// Parses an export line, as written in the export file, into its variable name and value.
+///
+/// For `PATH` exports, only the prepended directory is returned.
+pub(crate) fn parse_export(export: &str) -> Option<(String, String)> {
+    let (name, value) = if let Some(export) = export.strip_prefix("export ") {
+        export.split_once('=')?
+    } else {
+        export.strip_prefix("$Env:")?.split_once(" = ")?
+    };
+    let value = value.split('"').nth(1).unwrap_or(value.trim());
+    let value = if name == "PATH" {
+        value.trim_end_matches("$PATH").trim_end_matches([':', ';'])
+    } else {
+        value
+    };
+    Some((name.trim().to_string(), value.to_string()))
+}
+
+/// How the export file changes a variable of the current environment.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum EnvChangeKind {
+    /// The variable isn't set, or the `PATH` entry isn't in it.
+    Added,
+    /// The variable is set to another value.
+    Modified,
+    /// The variable is already set to the exported value, or the `PATH` entry is already in it.
+    Unchanged,
+}
+
+/// Change the export file makes to a variable of the current environment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EnvChange {
+    /// Name of the variable.
+    pub name: String,
+    /// Exported value, or, for `PATH`, the prepended entry.
+    pub value: String,
+    /// Value of the variable in the current environment, not set for `PATH` entries.
+    pub current: Option<String>,
+    /// How the variable is changed.
+    pub kind: EnvChangeKind,
+}
+
+/// Reads the exports of an export file.
+pub fn read_exports(export_file: &Path) -> Result<Vec<(String, String)>, Error> {
+    if !export_file.exists() {
+        return Err(Error::MissingExportFile(export_file.display().to_string()));
+    }
+    Ok(read_to_string(export_file)?
+        .lines()
+        .filter_map(parse_export)
+        .collect())
+}
+
+/// Returns the exports of an export file as JSON, with the entries `PATH` is prepended with as an
+/// ordered list, so they can be applied without parsing them.
+pub fn exports_to_json(exports: &[(String, String)]) -> Value {
+    let exports: Vec<Value> = exports
+        .iter()
+        .map(|(name, value)| {
+            if name == "PATH" {
+                let entries: Vec<&str> = value
+                    .split(PATH_SEPARATOR)
+                    .filter(|entry| !entry.is_empty())
+                    .collect();
+                json!({ "name": name, "value": entries })
+            } else {
+                json!({ "name": name, "value": value })
+            }
+        })
+        .collect();
+    json!(exports)
+}
+
+/// Compares the exports of an export file with the current environment, whose variables are
+/// returned by `current`.
+pub fn diff_exports(
+    exports: &[(String, String)],
+    current: impl Fn(&str) -> Option<String>,
+) -> Vec<EnvChange> {
+    let mut changes = Vec::new();
+    for (name, value) in exports {
+        if name == "PATH" {
+            let current_path = current("PATH").unwrap_or_default();
+            for entry in value
+                .split(PATH_SEPARATOR)
+                .filter(|entry| !entry.is_empty())
+            {
+                let kind = if current_path.split(PATH_SEPARATOR).any(|e| e == entry) {
+                    EnvChangeKind::Unchanged
+                } else {
+                    EnvChangeKind::Added
+                };
+                changes.push(EnvChange {
+                    name: name.clone(),
+                    value: entry.to_string(),
+                    current: None,
+                    kind,
+                });
+            }
+        } else {
+            let current = current(name);
+            let kind = match &current {
+                None => EnvChangeKind::Added,
+                Some(current) if current == value => EnvChangeKind::Unchanged,
+                Some(_) => EnvChangeKind::Modified,
+            };
+            changes.push(EnvChange {
+                name: name.clone(),
+                value: value.clone(),
+                current,
+                kind,
+            });
+        }
+    }
+    changes
+}
+
+/// Returns true if the line is one of the comments [`group_exports`] adds to the export file.
+fn is_group_comment(line: &str) -> bool {
+    line == COMMON_EXPORTS_COMMENT || line.starts_with(TARGET_EXPORTS_COMMENT)
+}
+
+/// Groups the exports of every component by the targets they are specific to.
+///
+/// Each group is a list of targets and the exports needed by them, an empty list of targets means
+/// the exports are common to every target. Common exports are kept first, in a single block, and
+/// target-specific ones follow under a comment naming their targets.
+pub fn group_exports(groups: &[(Vec<Target>, Vec<String>)]) -> Vec<String> {
+    let mut common = Vec::new();
+    let mut specific: Vec<(Vec<Target>, Vec<String>)> = Vec::new();
+    for (targets, exports) in groups.iter().filter(|(_, exports)| !exports.is_empty()) {
+        if targets.is_empty() {
+            common.extend(exports.iter().cloned());
+            continue;
+        }
+        let mut targets = targets.clone();
+        targets.sort_by_key(|target| *target as u8);
+        match specific.iter_mut().find(|(t, _)| *t == targets) {
+            Some((_, group)) => group.extend(exports.iter().cloned()),
+            None => specific.push((targets, exports.clone())),
+        }
+    }
+    if specific.is_empty() {
+        return common;
+    }
+
+    specific.sort_by_key(|(targets, _)| targets.iter().map(|t| *t as u8).collect::<Vec<_>>());
+    let mut lines = Vec::new();
+    if !common.is_empty() {
+        lines.push(COMMON_EXPORTS_COMMENT.to_string());
+        lines.extend(common);
+    }
+    for (targets, exports) in specific {
+        let targets: Vec<String> = targets.iter().map(Target::to_string).collect();
+        lines.push(format!("{TARGET_EXPORTS_COMMENT}{}", targets.join(", ")));
+        lines.extend(exports);
+    }
+    lines
+}
+
+/// Converts the exports, as generated for the host, to the syntax of another shell.
+pub fn convert_exports(exports: &[String], shell: ExportShell) -> Vec<String> {
+    exports
+        .iter()
+        .filter_map(|export| {
+            if is_group_comment(export) {
+                Some(export.clone())
+            } else {
+                parse_export(export).map(|(name, value)| shell.format_export(&name, &value))
+            }
+        })
+        .collect()
+}
 
 #[cfg(windows)]
 /// Sets an environment variable for the current user.
@@ -71,24 +304,120 @@ pub fn delete_env_variable(key: &str) -> Result<(), Error> {
     Ok(())
 }
 
+/// Returns the home directory of the current user.
+pub fn get_home_dir() -> Result<PathBuf, Error> {
+    BaseDirs::new()
+        .map(|base_dirs| base_dirs.home_dir().to_path_buf())
+        .ok_or(Error::HomeDirNotFound)
+}
+
+/// Returns true if espup runs as root, e.g. through `sudo`.
+#[cfg(unix)]
+pub fn is_root() -> bool {
+    Command::new("id")
+        .arg("-u")
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).trim() == "0")
+}
+
+/// Returns true if espup runs as root, which only applies to Unix.
+#[cfg(windows)]
+pub fn is_root() -> bool {
+    false
+}
+
+/// Returns the directory espup keeps its own files in, e.g. the `libclang` symlink.
+///
+/// Defaults to `~/.espup`. For read-only home directories, `ESPUP_CACHE_DIR` moves it to an `espup`
+/// subdirectory of the given directory, so removing it never touches files espup didn't create.
+pub fn get_espup_dir() -> Result<PathBuf, Error> {
+    let cache_dir = settings::current()
+        .settings
+        .cache_dir
+        .clone()
+        .map(OsString::from)
+        .or_else(|| env::var_os("ESPUP_CACHE_DIR"));
+    espup_dir(cache_dir, &get_home_dir()?)
+}
+
+/// Returns the espup directory for the given `ESPUP_CACHE_DIR` value and home directory.
+fn espup_dir(cache_dir: Option<OsString>, home_dir: &Path) -> Result<PathBuf, Error> {
+    match cache_dir {
+        Some(cache_dir) if !cache_dir.is_empty() => Ok(PathBuf::from(cache_dir).join("espup")),
+        _ => Ok(home_dir.join(".espup")),
+    }
+}
+
+/// Returns the path of the export file written when none is provided, the DEFAULT_EXPORT_FILE of the
+/// home directory, or of `ESPUP_CONFIG_DIR` for read-only home directories.
+pub fn default_export_file() -> Result<PathBuf, Error> {
+    Ok(config_dir(env::var_os("ESPUP_CONFIG_DIR"), &get_home_dir()?).join(DEFAULT_EXPORT_FILE))
+}
+
+/// Returns the directory of the default export file for the given `ESPUP_CONFIG_DIR` value and
+/// home directory.
+fn config_dir(config_dir: Option<OsString>, home_dir: &Path) -> PathBuf {
+    match config_dir {
+        Some(config_dir) if !config_dir.is_empty() => PathBuf::from(config_dir),
+        _ => home_dir.to_path_buf(),
+    }
+}
+
 /// Returns the absolute path to the export file, uses the DEFAULT_EXPORT_FILE if no arg is provided.
 pub fn get_export_file(export_file: Option<PathBuf>) -> Result<PathBuf, Error> {
-    if let Some(export_file) = export_file {
+    let export_file = if let Some(export_file) = export_file {
         if export_file.is_dir() {
             return Err(Error::InvalidDestination(export_file.display().to_string()));
         }
         if export_file.is_absolute() {
-            Ok(export_file)
+            export_file
         } else {
             let current_dir = env::current_dir()?;
-            Ok(current_dir.join(export_file))
+            current_dir.join(export_file)
         }
     } else {
-        Ok(BaseDirs::new()
-            .unwrap()
-            .home_dir()
-            .join(DEFAULT_EXPORT_FILE))
+        default_export_file()?
+    };
+    check_export_file_writable(&export_file)?;
+    Ok(export_file)
+}
+
+/// Checks that the export file can be written, so it fails before anything is downloaded.
+fn check_export_file_writable(export_file: &Path) -> Result<(), Error> {
+    let not_writable = || Error::ExportFileNotWritable(export_file.display().to_string());
+    if export_file
+        .metadata()
+        .is_ok_and(|metadata| metadata.permissions().readonly())
+    {
+        return Err(not_writable());
+    }
+    let parent = export_file.parent().ok_or_else(not_writable)?;
+    let read_only = parent
+        .metadata()
+        .map_or(true, |metadata| metadata.permissions().readonly());
+    if read_only || tempfile::tempfile_in(parent).is_err() {
+        return Err(not_writable());
     }
+    Ok(())
+}
+
+/// Checks that the export file, if it exists, was generated by espup so it can be overwritten.
+pub fn check_export_file(export_file: &Path, force: bool) -> Result<(), Error> {
+    if force || !export_file.exists() {
+        return Ok(());
+    }
+    let generated = read_to_string(export_file).is_ok_and(|contents| {
+        contents
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !is_group_comment(line))
+            .all(|line| parse_export(line).is_some())
+    });
+    if !generated {
+        return Err(Error::ExportFileNotGenerated(
+            export_file.display().to_string(),
+        ));
+    }
+    Ok(())
 }
 
 /// Creates the export file with the necessary environment variables.
@@ -155,46 +484,240 @@ pub fn set_env() -> Result<(), Error> {
     Ok(())
 }
 
-/// Instructions to export the environment variables.
-pub fn print_post_install_msg(export_file: &Path) -> Result<(), Error> {
+/// Shells whose profile can be modified to source the export file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, EnumIter)]
+#[strum(serialize_all = "lowercase")]
+pub enum ProfileShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl ProfileShell {
+    /// Detects the shell of the user from the `SHELL` environment variable.
+    pub fn detect() -> Result<Self, Error> {
+        let shell = env::var("SHELL").unwrap_or_default();
+        match Path::new(&shell).file_name().and_then(|name| name.to_str()) {
+            Some("bash") => Ok(ProfileShell::Bash),
+            Some("zsh") => Ok(ProfileShell::Zsh),
+            Some("fish") => Ok(ProfileShell::Fish),
+            _ => Err(Error::UnsupportedProfileShell(shell)),
+        }
+    }
+
+    /// Returns the shell the export file sourced by the profile is generated for.
+    pub fn export_shell(&self) -> ExportShell {
+        match self {
+            ProfileShell::Bash | ProfileShell::Zsh => ExportShell::Posix,
+            ProfileShell::Fish => ExportShell::Fish,
+        }
+    }
+
+    /// Returns the path of the profile that interactive shells read.
+    pub fn profile(&self) -> Result<PathBuf, Error> {
+        let home_dir = get_home_dir()?;
+        Ok(match self {
+            ProfileShell::Bash => home_dir.join(".bashrc"),
+            ProfileShell::Zsh => env::var_os("ZDOTDIR")
+                .map_or(home_dir, PathBuf::from)
+                .join(".zshrc"),
+            ProfileShell::Fish => env::var_os("XDG_CONFIG_HOME")
+                .map_or_else(|| home_dir.join(".config"), PathBuf::from)
+                .join("fish")
+                .join("config.fish"),
+        })
+    }
+}
+
+/// Returns the line that sources the export file from a profile.
+fn profile_line(export_file: &Path, shell: ProfileShell) -> String {
+    match shell {
+        ProfileShell::Bash | ProfileShell::Zsh => {
+            format!(". \"{}\" {PROFILE_COMMENT}", export_file.display())
+        }
+        ProfileShell::Fish => format!("source \"{}\" {PROFILE_COMMENT}", export_file.display()),
+    }
+}
+
+/// Backs up a profile, as `<profile>.bak`, before modifying it.
+fn backup_profile(profile: &Path) -> Result<(), Error> {
+    let mut backup = profile.as_os_str().to_owned();
+    backup.push(".bak");
+    debug!("Backing up '{}'", profile.display());
+    copy(profile, backup)?;
+    Ok(())
+}
+
+/// Adds a line sourcing the export file to the profile of the shell, returning false if it was
+/// already there.
+///
+/// The original profile, if any, is backed up as `<profile>.bak`.
+pub fn add_to_profile(
+    profile: &Path,
+    export_file: &Path,
+    shell: ProfileShell,
+) -> Result<bool, Error> {
+    let line = profile_line(export_file, shell);
+    let contents = if profile.exists() {
+        read_to_string(profile)?
+    } else {
+        String::new()
+    };
+    if contents.lines().any(|l| l == line) {
+        return Ok(false);
+    }
+
+    if profile.exists() {
+        backup_profile(profile)?;
+    } else if let Some(parent) = profile.parent() {
+        create_dir_all(parent).map_err(|_| Error::CreateDirectory(parent.display().to_string()))?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(profile)?;
+    if !contents.is_empty() && !contents.ends_with('\n') {
+        file.write_all(b"\n")?;
+    }
+    writeln!(file, "{line}")?;
+    Ok(true)
+}
+
+/// Returns the export file and its variants for the other shells, e.g. `export-esp.fish`.
+pub fn export_file_variants(export_file: &Path) -> Vec<PathBuf> {
+    ExportShell::value_variants()
+        .iter()
+        .map(|shell| export_file.with_extension(shell.extension()))
+        .chain(iter::once(export_file.to_path_buf()))
+        .collect()
+}
+
+/// Removes the lines espup added to a profile to source one of `export_files`, returning false
+/// if there were none.
+///
+/// The lines sourcing the export files of other installations are kept.
+pub fn remove_from_profile(profile: &Path, export_files: &[PathBuf]) -> Result<bool, Error> {
+    let Ok(contents) = read_to_string(profile) else {
+        return Ok(false);
+    };
+    let added_line = |line: &str| {
+        export_files.iter().any(|export_file| {
+            ProfileShell::iter().any(|shell| profile_line(export_file, shell) == line)
+        })
+    };
+    if !contents.lines().any(added_line) {
+        return Ok(false);
+    }
+
+    backup_profile(profile)?;
+    let mut lines: Vec<&str> = contents.lines().filter(|l| !added_line(l)).collect();
+    lines.push("");
+    write(profile, lines.join("\n"))?;
+    Ok(true)
+}
+
+/// Writes the instructions to export the environment variables into `out`.
+#[cfg_attr(unix, allow(unused_variables))]
+pub fn print_post_install_msg(
+    out: &mut dyn Write,
+    export_file: &Path,
+    modified_path: bool,
+    profile: Option<&Path>,
+) -> Result<(), Error> {
+    if let Some(profile) = profile {
+        writeln!(
+            out,
+            "\n\tThe environment variables are set up by '{}', open a new terminal for the changes to be effective",
+            profile.display()
+        )?;
+        return Ok(());
+    }
     #[cfg(windows)]
-    if cfg!(windows) {
-        println!(
+    if cfg!(windows) && !modified_path {
+        writeln!(
+            out,
+            "\n\tTo get started, you need to set up some environment variables by running: '{}'",
+            export_file.display()
+        )?;
+        writeln!(
+            out,
+            "\tThis step must be done every time you open a new terminal."
+        )?;
+    } else if cfg!(windows) {
+        writeln!(
+            out,
             "\n\tYour environments variables have been updated! Shell may need to be restarted for changes to be effective"
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "\tA file was created at '{}' showing the injected environment variables",
             export_file.display()
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "\tIf you get still get errors, try manually adding the environment variables by running '{}'",
             export_file.display()
-        );
+        )?;
     }
     #[cfg(unix)]
     if cfg!(unix) {
-        println!(
+        writeln!(
+            out,
             "\n\tTo get started, you need to set up some environment variables by running: '. {}'",
             export_file.display()
-        );
-        println!(
+        )?;
+        writeln!(
+            out,
             "\tThis step must be done every time you open a new terminal.\n\t    See other methods for setting the environment in https://github.com/esp-rs/espup/?tab=readme-ov-file#environment-variables-setup",
-        );
+        )?;
     }
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::env::{DEFAULT_EXPORT_FILE, create_export_file, get_export_file};
+    use crate::{
+        env::{
+            DEFAULT_EXPORT_FILE, EnvChangeKind, ExportShell, PATH_SEPARATOR, ProfileShell,
+            add_to_profile, check_export_file, config_dir, convert_exports, create_export_file,
+            diff_exports, espup_dir, export_file_variants, exports_to_json, get_export_file,
+            group_exports, parse_export, remove_from_profile,
+        },
+        error::Error,
+        targets::Target,
+    };
     use directories::BaseDirs;
+    use serde_json::json;
     use std::{
         env::current_dir,
-        fs::{create_dir_all, read_to_string},
-        path::PathBuf,
+        ffi::OsString,
+        fs::{create_dir_all, read_to_string, set_permissions, write},
+        path::{Path, PathBuf},
     };
     use tempfile::TempDir;
 
+    #[test]
+    fn test_get_espup_dir() {
+        let home_dir = Path::new("/home/user");
+        assert_eq!(espup_dir(None, home_dir).unwrap(), home_dir.join(".espup"));
+        assert_eq!(
+            espup_dir(Some(OsString::new()), home_dir).unwrap(),
+            home_dir.join(".espup")
+        );
+        assert_eq!(
+            espup_dir(Some(OsString::from("/tmp/cache")), home_dir).unwrap(),
+            Path::new("/tmp/cache/espup")
+        );
+    }
+
+    #[test]
+    fn test_config_dir() {
+        let home_dir = Path::new("/home/user");
+        assert_eq!(config_dir(None, home_dir), home_dir);
+        assert_eq!(config_dir(Some(OsString::new()), home_dir), home_dir);
+        assert_eq!(
+            config_dir(Some(OsString::from("/tmp/config")), home_dir),
+            Path::new("/tmp/config")
+        );
+    }
+
     #[test]
     #[allow(unused_variables)]
     fn test_get_export_file() {
@@ -210,13 +733,64 @@ mod tests {
             Ok(export_file)
         ));
         // Absolute path
-        let export_file = PathBuf::from("/home/user/export.sh");
-        assert!(matches!(
-            get_export_file(Some(PathBuf::from("/home/user/export.sh"))),
-            Ok(export_file)
-        ));
+        let temp_dir = TempDir::new().unwrap();
+        let export_file = temp_dir.path().join("export.sh");
+        assert_eq!(
+            get_export_file(Some(export_file.clone())).unwrap(),
+            export_file
+        );
         // Path is a directory instead of a file
         assert!(get_export_file(Some(home_dir)).is_err());
+        // Parent directory is missing
+        assert!(matches!(
+            get_export_file(Some(temp_dir.path().join("missing").join("export.sh"))),
+            Err(Error::ExportFileNotWritable(_))
+        ));
+        // Parent directory is read-only
+        let mut permissions = temp_dir.path().metadata().unwrap().permissions();
+        permissions.set_readonly(true);
+        set_permissions(temp_dir.path(), permissions.clone()).unwrap();
+        assert!(matches!(
+            get_export_file(Some(export_file)),
+            Err(Error::ExportFileNotWritable(_))
+        ));
+        #[allow(clippy::permissions_set_readonly_false)]
+        permissions.set_readonly(false);
+        set_permissions(temp_dir.path(), permissions).unwrap();
+    }
+
+    #[test]
+    fn test_modify_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let profile = temp_dir.path().join(".bashrc");
+        let export_file = temp_dir.path().join("export-esp.sh");
+        let original = "alias ll='ls -l'";
+        write(&profile, original).unwrap();
+
+        assert!(add_to_profile(&profile, &export_file, ProfileShell::Bash).unwrap());
+        // The line is only added once
+        assert!(!add_to_profile(&profile, &export_file, ProfileShell::Bash).unwrap());
+        let contents = read_to_string(&profile).unwrap();
+        assert_eq!(contents.lines().count(), 2);
+        assert!(contents.contains(&format!(". \"{}\"", export_file.display())));
+        assert_eq!(
+            read_to_string(temp_dir.path().join(".bashrc.bak")).unwrap(),
+            original
+        );
+
+        // The lines of other installations are kept
+        let other_export_file = temp_dir.path().join("export-other.sh");
+        assert!(add_to_profile(&profile, &other_export_file, ProfileShell::Bash).unwrap());
+        let export_files = export_file_variants(&export_file);
+        assert!(remove_from_profile(&profile, &export_files).unwrap());
+        assert!(!remove_from_profile(&profile, &export_files).unwrap());
+        assert!(remove_from_profile(&profile, &export_file_variants(&other_export_file)).unwrap());
+        assert_eq!(read_to_string(&profile).unwrap(), format!("{original}\n"));
+
+        // Missing profiles are created
+        let profile = temp_dir.path().join("fish").join("config.fish");
+        assert!(add_to_profile(&profile, &export_file, ProfileShell::Fish).unwrap());
+        assert!(read_to_string(&profile).unwrap().starts_with("source "));
     }
 
     #[test]
@@ -242,4 +816,163 @@ mod tests {
         ];
         assert!(create_export_file(&export_file, &exports).is_err());
     }
+
+    #[test]
+    fn test_parse_export() {
+        assert_eq!(
+            parse_export("export LIBCLANG_PATH=\"/home/user/.rustup/lib\""),
+            Some((
+                "LIBCLANG_PATH".to_string(),
+                "/home/user/.rustup/lib".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_export("export PATH=\"/home/user/gcc/bin:$PATH\""),
+            Some(("PATH".to_string(), "/home/user/gcc/bin".to_string()))
+        );
+        assert_eq!(
+            parse_export("$Env:LIBCLANG_PATH = \"C:\\esp\\libclang.dll\""),
+            Some((
+                "LIBCLANG_PATH".to_string(),
+                "C:\\esp\\libclang.dll".to_string()
+            ))
+        );
+        assert_eq!(
+            parse_export("$Env:PATH = \"C:\\esp\\bin;\" + $Env:PATH"),
+            Some(("PATH".to_string(), "C:\\esp\\bin".to_string()))
+        );
+        assert_eq!(parse_export("echo hello"), None);
+    }
+
+    #[test]
+    fn test_diff_exports() {
+        let exports = vec![
+            ("LIBCLANG_PATH".to_string(), "/esp/lib".to_string()),
+            ("CLANG_PATH".to_string(), "/esp/bin/clang".to_string()),
+            ("RUSTUP_TOOLCHAIN".to_string(), "esp".to_string()),
+            ("PATH".to_string(), "/esp/gcc/bin".to_string()),
+            ("PATH".to_string(), "/esp/riscv/bin".to_string()),
+        ];
+        let current = |name: &str| match name {
+            "CLANG_PATH" => Some("/usr/bin/clang".to_string()),
+            "RUSTUP_TOOLCHAIN" => Some("esp".to_string()),
+            "PATH" => Some(["/usr/bin", "/esp/riscv/bin"].join(PATH_SEPARATOR)),
+            _ => None,
+        };
+        let changes = diff_exports(&exports, current);
+        let changes: Vec<(&str, &str, EnvChangeKind)> = changes
+            .iter()
+            .map(|change| (change.name.as_str(), change.value.as_str(), change.kind))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("LIBCLANG_PATH", "/esp/lib", EnvChangeKind::Added),
+                ("CLANG_PATH", "/esp/bin/clang", EnvChangeKind::Modified),
+                ("RUSTUP_TOOLCHAIN", "esp", EnvChangeKind::Unchanged),
+                ("PATH", "/esp/gcc/bin", EnvChangeKind::Added),
+                ("PATH", "/esp/riscv/bin", EnvChangeKind::Unchanged),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_exports_to_json() {
+        let exports = vec![
+            ("LIBCLANG_PATH".to_string(), "/esp/lib".to_string()),
+            (
+                "PATH".to_string(),
+                format!("/esp/xtensa/bin{PATH_SEPARATOR}/esp/riscv/bin"),
+            ),
+        ];
+        assert_eq!(
+            exports_to_json(&exports),
+            json!([
+                { "name": "LIBCLANG_PATH", "value": "/esp/lib" },
+                { "name": "PATH", "value": ["/esp/xtensa/bin", "/esp/riscv/bin"] },
+            ])
+        );
+    }
+
+    #[test]
+    fn test_convert_exports() {
+        let exports = vec![
+            "export LIBCLANG_PATH=\"/esp/lib\"".to_string(),
+            "export PATH=\"/esp/gcc/bin:$PATH\"".to_string(),
+        ];
+        assert_eq!(
+            convert_exports(&exports, ExportShell::Fish),
+            vec![
+                "set -gx LIBCLANG_PATH \"/esp/lib\"".to_string(),
+                "set -gx PATH \"/esp/gcc/bin\" $PATH".to_string(),
+            ]
+        );
+        #[cfg(unix)]
+        {
+            assert_eq!(convert_exports(&exports, ExportShell::Posix), exports);
+            assert_eq!(
+                convert_exports(&exports, ExportShell::Powershell),
+                vec![
+                    "$Env:LIBCLANG_PATH = \"/esp/lib\"".to_string(),
+                    "$Env:PATH = \"/esp/gcc/bin:\" + $Env:PATH".to_string(),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_group_exports() {
+        let llvm = "export LIBCLANG_PATH=\"/esp/lib\"".to_string();
+        let xtensa_gcc = "export PATH=\"/esp/xtensa-esp-elf/bin:$PATH\"".to_string();
+        let riscv_gcc = "export PATH=\"/esp/riscv32-esp-elf/bin:$PATH\"".to_string();
+        // Only common exports
+        assert_eq!(
+            group_exports(&[(vec![], vec![llvm.clone()]), (vec![Target::ESP32], vec![])]),
+            vec![llvm.clone()]
+        );
+        let lines = group_exports(&[
+            (vec![Target::ESP32C3], vec![riscv_gcc.clone()]),
+            (vec![], vec![llvm.clone()]),
+            (
+                vec![Target::ESP32S3, Target::ESP32],
+                vec![xtensa_gcc.clone()],
+            ),
+        ]);
+        assert_eq!(
+            lines,
+            vec![
+                "# Common to all targets".to_string(),
+                llvm,
+                "# Targets: esp32, esp32s3".to_string(),
+                xtensa_gcc,
+                "# Targets: esp32c3".to_string(),
+                riscv_gcc,
+            ]
+        );
+        assert_eq!(convert_exports(&lines, ExportShell::Fish)[0], lines[0]);
+
+        let temp_dir = TempDir::new().unwrap();
+        let export_file = temp_dir.path().join("export.sh");
+        create_export_file(&export_file, &lines).unwrap();
+        assert!(check_export_file(&export_file, false).is_ok());
+    }
+
+    #[test]
+    fn test_check_export_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let export_file = temp_dir.path().join("export.sh");
+        // Missing file
+        assert!(check_export_file(&export_file, false).is_ok());
+        // Generated file
+        create_export_file(
+            &export_file,
+            &["export LIBCLANG_PATH=\"/esp/lib\"".to_string()],
+        )
+        .unwrap();
+        assert!(check_export_file(&export_file, false).is_ok());
+        // Hand-edited file
+        std::fs::write(&export_file, "export FOO=bar\nalias ll='ls -l'\n").unwrap();
+        assert!(check_export_file(&export_file, false).is_err());
+        assert!(check_export_file(&export_file, true).is_ok());
+    }
 }
@@ -0,0 +1,319 @@
+//! Detection of the chips connected through a serial port, using the ROM bootloader protocol.
+
+use crate::{error::Error, targets::Target};
+use log::debug;
+use serialport::{ClearBuffer, SerialPort, SerialPortType};
+use std::{
+    collections::HashSet,
+    io,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+/// Baud rate the ROM bootloader starts with.
+const BAUD_RATE: u32 = 115_200;
+/// USB vendor and product IDs of the USB-Serial-JTAG peripheral of the newer chips.
+const USB_SERIAL_JTAG: (u16, u16) = (0x303a, 0x1001);
+/// USB vendor ID of Espressif, whose chips with a USB peripheral are probed regardless of the
+/// product ID.
+const ESPRESSIF_VID: u16 = 0x303a;
+/// USB vendor and product IDs of the USB-to-UART bridges of the development boards.
+const USB_UART_BRIDGES: [(u16, u16); 8] = [
+    // Silicon Labs CP210x
+    (0x10c4, 0xea60),
+    // WCH CH340, CH343 and CH9102
+    (0x1a86, 0x7523),
+    (0x1a86, 0x55d3),
+    (0x1a86, 0x55d4),
+    // FTDI FT232R, FT2232H, FT4232H and FT231X
+    (0x0403, 0x6001),
+    (0x0403, 0x6010),
+    (0x0403, 0x6011),
+    (0x0403, 0x6015),
+];
+/// Time to wait for each response of the bootloader.
+const RESPONSE_TIMEOUT: Duration = Duration::from_millis(100);
+/// Times the bootloader is synchronized with before giving up.
+const SYNC_ATTEMPTS: usize = 7;
+
+/// Bootloader command to synchronize with it.
+const SYNC: u8 = 0x08;
+/// Bootloader command to read a register.
+const READ_REG: u8 = 0x0a;
+/// Bootloader command to read the security information, which includes the chip ID.
+const GET_SECURITY_INFO: u8 = 0x14;
+/// Register whose value identifies the chips that don't report a chip ID.
+const CHIP_DETECT_MAGIC_REG_ADDR: u32 = 0x4000_1000;
+
+/// SLIP frame delimiter.
+const SLIP_END: u8 = 0xc0;
+/// SLIP escape byte.
+const SLIP_ESC: u8 = 0xdb;
+
+/// Detects the chip of the single board connected through a serial port.
+///
+/// Probing resets the boards, so only the ports of Espressif chips and USB-to-UART bridges are
+/// probed, on a blocking thread.
+pub async fn detect_connected_target() -> Result<Target, Error> {
+    tokio::task::spawn_blocking(probe_ports)
+        .await
+        .unwrap_or_else(|e| {
+            debug!("Failed to probe the serial ports: {e}");
+            Err(Error::NoConnectedTarget)
+        })
+}
+
+/// Returns true if a USB serial port may belong to an Espressif development board.
+fn is_espressif_port(vid: u16, pid: u16) -> bool {
+    vid == ESPRESSIF_VID || USB_UART_BRIDGES.contains(&(vid, pid))
+}
+
+/// Identifies the chips connected to the serial ports of Espressif development boards.
+fn probe_ports() -> Result<Target, Error> {
+    let ports = serialport::available_ports().unwrap_or_default();
+    let mut targets = HashSet::new();
+    for port in ports {
+        let SerialPortType::UsbPort(usb) = port.port_type else {
+            continue;
+        };
+        if !is_espressif_port(usb.vid, usb.pid) {
+            debug!(
+                "Skipping serial port '{}' ({:04x}:{:04x})",
+                port.port_name, usb.vid, usb.pid
+            );
+            continue;
+        }
+        debug!("Probing serial port '{}'", port.port_name);
+        match probe_port(&port.port_name, (usb.vid, usb.pid) == USB_SERIAL_JTAG) {
+            Ok(Some(target)) => {
+                debug!("Detected {target} on '{}'", port.port_name);
+                targets.insert(target);
+            }
+            Ok(None) => debug!("No known chip detected on '{}'", port.port_name),
+            Err(e) => debug!("Failed to probe '{}': {e}", port.port_name),
+        }
+    }
+
+    match targets.len() {
+        0 => Err(Error::NoConnectedTarget),
+        1 => Ok(targets.into_iter().next().unwrap()),
+        _ => {
+            let mut names: Vec<String> = targets.iter().map(ToString::to_string).collect();
+            names.sort();
+            Err(Error::MultipleConnectedTargets(names.join(", ")))
+        }
+    }
+}
+
+/// Resets the chip connected to a port into its bootloader and identifies it.
+fn probe_port(port_name: &str, usb_serial_jtag: bool) -> io::Result<Option<Target>> {
+    let mut port = serialport::new(port_name, BAUD_RATE)
+        .timeout(RESPONSE_TIMEOUT)
+        .open()?;
+    reset_to_bootloader(port.as_mut(), usb_serial_jtag)?;
+    if !sync(port.as_mut())? {
+        return Ok(None);
+    }
+
+    // Chips that report their ID are identified by it, the older ones by a magic register value
+    if let Ok((_, data)) = command(port.as_mut(), GET_SECURITY_INFO, &[])
+        && let Some(chip_id) = data.get(12..16)
+    {
+        let chip_id = u32::from_le_bytes(chip_id.try_into().unwrap());
+        if let Some(target) = target_from_chip_id(chip_id) {
+            return Ok(Some(target));
+        }
+    }
+    let (magic, _) = command(
+        port.as_mut(),
+        READ_REG,
+        &CHIP_DETECT_MAGIC_REG_ADDR.to_le_bytes(),
+    )?;
+    Ok(target_from_magic_value(magic))
+}
+
+/// Resets the chip into the bootloader by toggling the DTR (GPIO0) and RTS (EN) lines.
+fn reset_to_bootloader(port: &mut dyn SerialPort, usb_serial_jtag: bool) -> io::Result<()> {
+    let delay = Duration::from_millis(100);
+    if usb_serial_jtag {
+        port.write_request_to_send(false)?;
+        port.write_data_terminal_ready(false)?;
+        sleep(delay);
+        port.write_data_terminal_ready(true)?;
+        port.write_request_to_send(false)?;
+        sleep(delay);
+        port.write_request_to_send(true)?;
+        port.write_data_terminal_ready(false)?;
+        port.write_request_to_send(true)?;
+        sleep(delay);
+        port.write_request_to_send(false)?;
+        port.write_data_terminal_ready(false)?;
+    } else {
+        port.write_data_terminal_ready(false)?;
+        port.write_request_to_send(true)?;
+        sleep(delay);
+        port.write_data_terminal_ready(true)?;
+        port.write_request_to_send(false)?;
+        sleep(Duration::from_millis(50));
+        port.write_data_terminal_ready(false)?;
+    }
+    port.clear(ClearBuffer::Input)?;
+    Ok(())
+}
+
+/// Synchronizes with the bootloader, returning false if it never answers.
+fn sync(port: &mut dyn SerialPort) -> io::Result<bool> {
+    let mut data = vec![0x07, 0x07, 0x12, 0x20];
+    data.extend([0x55; 32]);
+    for _ in 0..SYNC_ATTEMPTS {
+        if command(port, SYNC, &data).is_ok() {
+            // The bootloader answers every synchronization more than once
+            sleep(RESPONSE_TIMEOUT);
+            port.clear(ClearBuffer::Input)?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Sends a command to the bootloader, returning the value and data of its response.
+fn command(port: &mut dyn SerialPort, command: u8, data: &[u8]) -> io::Result<(u32, Vec<u8>)> {
+    let mut packet = vec![0x00, command];
+    packet.extend((data.len() as u16).to_le_bytes());
+    packet.extend(0u32.to_le_bytes());
+    packet.extend(data);
+    port.write_all(&slip_encode(&packet))?;
+    port.flush()?;
+
+    let deadline = Instant::now() + RESPONSE_TIMEOUT * 3;
+    while Instant::now() < deadline {
+        let response = read_frame(port, deadline)?;
+        // Responses of previous commands are skipped
+        if response.len() < 8 || response[0] != 0x01 || response[1] != command {
+            continue;
+        }
+        let value = u32::from_le_bytes(response[4..8].try_into().unwrap());
+        let data = response[8..].to_vec();
+        // The data ends with the status bytes, two of them, or four for the ESP32
+        let status = match data.len() {
+            len if len >= 4 && len % 4 == 0 => &data[len - 4..],
+            len if len >= 2 => &data[len - 2..],
+            _ => return Err(io::ErrorKind::InvalidData.into()),
+        };
+        if status[0] != 0 {
+            return Err(io::ErrorKind::Unsupported.into());
+        }
+        return Ok((value, data));
+    }
+    Err(io::ErrorKind::TimedOut.into())
+}
+
+/// Reads a SLIP frame, without its delimiters.
+fn read_frame(port: &mut dyn SerialPort, deadline: Instant) -> io::Result<Vec<u8>> {
+    let mut frame = Vec::new();
+    let mut started = false;
+    let mut escaped = false;
+    let mut byte = [0u8];
+    loop {
+        // The boot messages printed before the bootloader answers are not framed
+        if Instant::now() >= deadline {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        port.read_exact(&mut byte)?;
+        match (byte[0], started, escaped) {
+            (SLIP_END, false, _) => started = true,
+            (SLIP_END, true, _) if frame.is_empty() => {}
+            (SLIP_END, true, _) => return Ok(frame),
+            (_, false, _) => {}
+            (SLIP_ESC, true, false) => escaped = true,
+            (0xdc, true, true) => {
+                frame.push(SLIP_END);
+                escaped = false;
+            }
+            (0xdd, true, true) => {
+                frame.push(SLIP_ESC);
+                escaped = false;
+            }
+            (_, true, true) => return Err(io::ErrorKind::InvalidData.into()),
+            (byte, true, false) => frame.push(byte),
+        }
+    }
+}
+
+/// Encodes a packet into a SLIP frame.
+fn slip_encode(packet: &[u8]) -> Vec<u8> {
+    let mut frame = vec![SLIP_END];
+    for &byte in packet {
+        match byte {
+            SLIP_END => frame.extend([SLIP_ESC, 0xdc]),
+            SLIP_ESC => frame.extend([SLIP_ESC, 0xdd]),
+            byte => frame.push(byte),
+        }
+    }
+    frame.push(SLIP_END);
+    frame
+}
+
+/// Returns the target of a chip ID, as reported by `GET_SECURITY_INFO`.
+fn target_from_chip_id(chip_id: u32) -> Option<Target> {
+    match chip_id {
+        5 => Some(Target::ESP32C3),
+        9 => Some(Target::ESP32S3),
+        12 => Some(Target::ESP32C2),
+        13 => Some(Target::ESP32C6),
+        16 => Some(Target::ESP32H2),
+        18 => Some(Target::ESP32P4),
+        20 => Some(Target::ESP32C61),
+        23 => Some(Target::ESP32C5),
+        _ => None,
+    }
+}
+
+/// Returns the target of a chip detect magic register value.
+fn target_from_magic_value(magic: u32) -> Option<Target> {
+    match magic {
+        0x00f0_1d83 => Some(Target::ESP32),
+        0x0000_07c6 => Some(Target::ESP32S2),
+        0x0000_0009 => Some(Target::ESP32S3),
+        0x6921_506f | 0x1b31_506f | 0x4881_606f | 0x4361_606f => Some(Target::ESP32C3),
+        0x6f51_306f | 0x7c41_a06f => Some(Target::ESP32C2),
+        0x2ce0_806f => Some(Target::ESP32C6),
+        0xd7b7_3e80 => Some(Target::ESP32H2),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        hardware::{is_espressif_port, slip_encode, target_from_chip_id, target_from_magic_value},
+        targets::Target,
+    };
+
+    #[test]
+    fn test_slip_encode() {
+        assert_eq!(
+            slip_encode(&[0x01, 0xc0, 0xdb, 0x02]),
+            vec![0xc0, 0x01, 0xdb, 0xdc, 0xdb, 0xdd, 0x02, 0xc0]
+        );
+    }
+
+    #[test]
+    fn test_is_espressif_port() {
+        assert!(is_espressif_port(0x303a, 0x1001));
+        assert!(is_espressif_port(0x303a, 0x0002));
+        assert!(is_espressif_port(0x10c4, 0xea60));
+        // Arduino Uno
+        assert!(!is_espressif_port(0x2341, 0x0043));
+    }
+
+    #[test]
+    fn test_target_detection() {
+        assert_eq!(target_from_magic_value(0x00f01d83), Some(Target::ESP32));
+        assert_eq!(target_from_magic_value(0x000007c6), Some(Target::ESP32S2));
+        assert_eq!(target_from_magic_value(0xdeadbeef), None);
+        assert_eq!(target_from_chip_id(13), Some(Target::ESP32C6));
+        assert_eq!(target_from_chip_id(23), Some(Target::ESP32C5));
+        assert_eq!(target_from_chip_id(0), None);
+    }
+}
@@ -2,8 +2,9 @@
 
 use crate::error::Error;
 use guess_host_triple::guess_host_triple;
+use log::debug;
 use miette::Result;
-use std::str::FromStr;
+use std::{process::Command, str::FromStr};
 use strum::{Display, EnumString};
 
 #[derive(Display, Debug, Clone, EnumString, Default)]
@@ -15,6 +16,12 @@ pub enum HostTriple {
     /// ARM64 Linux
     #[strum(serialize = "aarch64-unknown-linux-gnu")]
     Aarch64UnknownLinuxGnu,
+    /// 64-bit Linux with musl libc
+    #[strum(serialize = "x86_64-unknown-linux-musl")]
+    X86_64UnknownLinuxMusl,
+    /// ARM64 Linux with musl libc
+    #[strum(serialize = "aarch64-unknown-linux-musl")]
+    Aarch64UnknownLinuxMusl,
     /// 64-bit MSVC
     #[strum(serialize = "x86_64-pc-windows-msvc")]
     X86_64PcWindowsMsvc,
@@ -29,20 +36,87 @@ pub enum HostTriple {
     Aarch64AppleDarwin,
 }
 
+impl HostTriple {
+    /// Returns true if the host uses musl libc, for which no toolchain assets are released.
+    pub fn is_musl(&self) -> bool {
+        matches!(
+            self,
+            HostTriple::X86_64UnknownLinuxMusl | HostTriple::Aarch64UnknownLinuxMusl
+        )
+    }
+
+    /// Returns true if the host runs Windows.
+    pub fn is_windows(&self) -> bool {
+        matches!(
+            self,
+            HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu
+        )
+    }
+}
+
 /// Parse the host triple if specified, otherwise guess it.
+///
+/// The guessed triple is the one espup was built for, so on Linux its libc is replaced with the
+/// one of the running system, e.g. a musl build of espup running on a glibc distribution.
 pub fn get_host_triple(host_triple_arg: Option<String>) -> Result<HostTriple, Error> {
-    let host_triple = if let Some(host_triple) = &host_triple_arg {
-        host_triple
-    } else {
-        guess_host_triple().unwrap()
+    let host_triple = match host_triple_arg {
+        Some(host_triple) => host_triple,
+        None => {
+            let guessed = guess_host_triple().unwrap();
+            match system_libc_is_musl() {
+                Some(musl) => with_libc(guessed, musl),
+                None => guessed.to_string(),
+            }
+        }
     };
 
-    HostTriple::from_str(host_triple).map_err(|_| Error::UnsupportedHostTriple(host_triple.into()))
+    HostTriple::from_str(&host_triple).map_err(|_| Error::UnsupportedHostTriple(host_triple))
+}
+
+/// Returns whether the libc of the running Linux system is musl, as reported by `ldd`, or `None`
+/// when it can't be told.
+fn system_libc_is_musl() -> Option<bool> {
+    if !cfg!(target_os = "linux") {
+        return None;
+    }
+    // musl prints its version to stderr, glibc to stdout
+    let output = Command::new("ldd").arg("--version").output().ok()?;
+    let musl = ldd_reports_musl(&format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    ));
+    debug!(
+        "Detected libc: {:?}",
+        musl.map(|musl| if musl { "musl" } else { "glibc" })
+    );
+    musl
+}
+
+/// Returns whether the output of `ldd --version` is the one of musl or glibc.
+fn ldd_reports_musl(output: &str) -> Option<bool> {
+    let output = output.to_lowercase();
+    if output.contains("musl") {
+        Some(true)
+    } else if output.contains("glibc") || output.contains("gnu libc") {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// Replaces the libc of a Linux host triple, other host triples are returned unchanged.
+fn with_libc(host_triple: &str, musl: bool) -> String {
+    let libc = if musl { "musl" } else { "gnu" };
+    match host_triple.rsplit_once("-linux-") {
+        Some((arch, _)) => format!("{arch}-linux-{libc}"),
+        None => host_triple.to_string(),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::host_triple::{HostTriple, get_host_triple};
+    use crate::host_triple::{HostTriple, get_host_triple, ldd_reports_musl, with_libc};
 
     #[test]
     fn test_get_host_triple() {
@@ -54,6 +128,14 @@ mod tests {
             get_host_triple(Some("aarch64-unknown-linux-gnu".to_string())),
             Ok(HostTriple::Aarch64UnknownLinuxGnu)
         ));
+        assert!(matches!(
+            get_host_triple(Some("x86_64-unknown-linux-musl".to_string())),
+            Ok(HostTriple::X86_64UnknownLinuxMusl)
+        ));
+        assert!(matches!(
+            get_host_triple(Some("aarch64-unknown-linux-musl".to_string())),
+            Ok(HostTriple::Aarch64UnknownLinuxMusl)
+        ));
         assert!(matches!(
             get_host_triple(Some("x86_64-pc-windows-msvc".to_string())),
             Ok(HostTriple::X86_64PcWindowsMsvc)
@@ -72,18 +154,62 @@ mod tests {
         ));
 
         assert!(get_host_triple(Some("some-fake-triple".to_string())).is_err());
+    }
+
+    #[test]
+    fn test_detect_libc() {
+        assert_eq!(
+            ldd_reports_musl("musl libc (x86_64)\nVersion 1.2.5\nDynamic Program Loader\n"),
+            Some(true)
+        );
+        assert_eq!(
+            ldd_reports_musl("ldd (Ubuntu GLIBC 2.39-0ubuntu8.4) 2.39\nCopyright (C) 2024\n"),
+            Some(false)
+        );
+        assert_eq!(
+            ldd_reports_musl("ldd (GNU libc) 2.41\nCopyright (C) 2025\n"),
+            Some(false)
+        );
+        assert_eq!(ldd_reports_musl(""), None);
+
+        assert_eq!(
+            with_libc("x86_64-unknown-linux-gnu", true),
+            "x86_64-unknown-linux-musl"
+        );
+        assert_eq!(
+            with_libc("aarch64-unknown-linux-musl", false),
+            "aarch64-unknown-linux-gnu"
+        );
+        assert_eq!(
+            with_libc("aarch64-apple-darwin", true),
+            "aarch64-apple-darwin"
+        );
+    }
 
-        // Guessed Host Triples
-        #[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+    #[test]
+    fn test_guess_host_triple() {
+        #[cfg(all(target_os = "linux", target_arch = "aarch64", target_env = "gnu"))]
         assert!(matches!(
             get_host_triple(None),
             Ok(HostTriple::Aarch64UnknownLinuxGnu)
         ));
-        #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+        #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "gnu"))]
         assert!(matches!(
             get_host_triple(None),
             Ok(HostTriple::X86_64UnknownLinuxGnu)
         ));
+        // Static musl builds also run on glibc systems, whose libc is detected
+        #[cfg(all(target_os = "linux", target_arch = "aarch64", target_env = "musl"))]
+        assert!(matches!(
+            get_host_triple(None),
+            Ok(HostTriple::Aarch64UnknownLinuxMusl | HostTriple::Aarch64UnknownLinuxGnu)
+        ));
+        // Static musl builds also run on glibc systems, whose libc is detected
+        #[cfg(all(target_os = "linux", target_arch = "x86_64", target_env = "musl"))]
+        assert!(matches!(
+            get_host_triple(None),
+            Ok(HostTriple::X86_64UnknownLinuxMusl | HostTriple::X86_64UnknownLinuxGnu)
+        ));
         #[cfg(all(target_os = "windows", target_arch = "x86_64", target_env = "msvc"))]
         assert!(matches!(
             get_host_triple(None),
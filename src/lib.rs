@@ -2,28 +2,100 @@ pub mod cli;
 mod completion_shell;
 pub mod env;
 pub mod error;
+pub mod hardware;
 pub mod host_triple;
+pub mod project;
+pub mod report;
 pub mod targets;
 pub mod toolchain;
+pub mod vscode;
+
+pub use toolchain::{
+    InstallConfig, InstallMode, InstallSummary, install, progress::ProgressReporter,
+    settings::Settings,
+};
 
 pub mod logging {
+    use clap::ColorChoice;
     use env_logger::{Builder, Env, WriteStyle};
+    use log::Level;
+    use miette::MietteHandlerOpts;
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    };
 
     use crate::toolchain::PROCESS_BARS;
 
+    static COLOR: Mutex<ColorChoice> = Mutex::new(ColorChoice::Auto);
+    static UNICODE: AtomicBool = AtomicBool::new(true);
+
+    /// Sets whether the logs and error diagnostics are colored, `auto` colors them only when the
+    /// terminal supports it and `NO_COLOR` is not set.
+    pub fn set_color(color: ColorChoice) {
+        *COLOR.lock().unwrap() = color;
+        if color != ColorChoice::Auto {
+            set_diagnostic_hook();
+        }
+    }
+
+    /// Sets whether the logs are prefixed with emoji and the error diagnostics drawn with Unicode
+    /// symbols, or only ASCII characters are used for consoles that can't render them.
+    pub fn set_unicode(unicode: bool) {
+        UNICODE.store(unicode, Ordering::Relaxed);
+        if !unicode {
+            set_diagnostic_hook();
+        }
+    }
+
+    /// Renders the error diagnostics with the configured colors and symbols.
+    fn set_diagnostic_hook() {
+        // The hook can only be set once, so the settings are read when a diagnostic is rendered
+        let _ = miette::set_hook(Box::new(|_| {
+            let mut opts = MietteHandlerOpts::new().unicode(UNICODE.load(Ordering::Relaxed));
+            let color = *COLOR.lock().unwrap();
+            if color != ColorChoice::Auto {
+                opts = opts.color(color == ColorChoice::Always);
+            }
+            Box::new(opts.build())
+        }));
+    }
+
+    /// Returns the prefix of the logs of a level, an emoji or an ASCII tag like `[INFO]`.
+    fn level_prefix(level: Level, unicode: bool) -> &'static str {
+        match (level, unicode) {
+            (Level::Error, true) => "⛔",
+            (Level::Warn, true) => "⚠️ ",
+            (Level::Info, true) => "💡",
+            (Level::Debug, true) => "🐛",
+            (Level::Trace, true) => "🔍",
+            (Level::Error, false) => "[ERROR]",
+            (Level::Warn, false) => "[WARN]",
+            (Level::Info, false) => "[INFO]",
+            (Level::Debug, false) => "[DEBUG]",
+            (Level::Trace, false) => "[TRACE]",
+        }
+    }
+
     /// Initializes the logger
     pub fn initialize_logger(log_level: &str) {
+        let write_style = match *COLOR.lock().unwrap() {
+            ColorChoice::Auto => WriteStyle::Auto,
+            ColorChoice::Always => WriteStyle::Always,
+            ColorChoice::Never => WriteStyle::Never,
+        };
         let logger = Builder::from_env(Env::default().default_filter_or(log_level))
             .format(|buf, record| {
                 use std::io::Write;
+                let unicode = UNICODE.load(Ordering::Relaxed);
                 writeln!(
                     buf,
-                    "[{}]: {}",
-                    record.level().to_string().to_lowercase(),
+                    "{} {}",
+                    level_prefix(record.level(), unicode),
                     record.args()
                 )
             })
-            .write_style(WriteStyle::Always)
+            .write_style(write_style)
             .build();
         let level = logger.filter();
         // make logging and process bar no longer mixed up
@@ -32,6 +104,51 @@ pub mod logging {
             .unwrap();
         log::set_max_level(level);
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::level_prefix;
+        use log::Level;
+
+        #[test]
+        fn test_level_prefix() {
+            assert_eq!(level_prefix(Level::Warn, true), "⚠️ ");
+            assert_eq!(level_prefix(Level::Info, true), "💡");
+            for level in [
+                Level::Error,
+                Level::Warn,
+                Level::Info,
+                Level::Debug,
+                Level::Trace,
+            ] {
+                let tag = level_prefix(level, false);
+                assert!(tag.is_ascii());
+                assert_eq!(tag, format!("[{}]", level.as_str()));
+            }
+        }
+    }
+}
+
+pub mod prompt {
+    use crate::error::Error;
+    use std::io::{self, BufRead, IsTerminal, Write};
+
+    /// Asks the user to confirm an operation, which is always confirmed when `yes` is set.
+    ///
+    /// When stdin is not a terminal there is nobody to answer, so `yes` is required instead.
+    pub fn confirm(question: &str, yes: bool) -> Result<bool, Error> {
+        if yes {
+            return Ok(true);
+        }
+        if !io::stdin().is_terminal() {
+            return Err(Error::ConfirmationRequired);
+        }
+        print!("{question} [y/N] ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
 }
 
 pub mod update {
@@ -1,50 +1,332 @@
-use clap::{CommandFactory, Parser};
+use clap::{ColorChoice, CommandFactory, Parser, ValueEnum, builder::BoolishValueParser};
+use directories::BaseDirs;
 use espup::{
-    cli::{CompletionsOpts, InstallOpts, UninstallOpts},
-    logging::initialize_logger,
+    cli::{
+        AddTargetOpts, CacheKeyOpts, CompletionsOpts, ConfigureOpts, EnvOpts, InstallOpts,
+        ReportOpts, TargetsOpts, UninstallOpts, UpdateOpts, VerifyOpts,
+    },
+    env::{
+        EnvChangeKind, ExportShell, ProfileShell, add_to_profile, default_export_file,
+        diff_exports, export_file_variants, exports_to_json, get_espup_dir, get_export_file,
+        get_home_dir, is_root, print_post_install_msg, read_exports, remove_from_profile,
+    },
+    error::Error,
+    hardware::detect_connected_target,
+    logging::{initialize_logger, set_color, set_unicode},
+    project::{detect_targets as detect_project_targets, write_toolchain_file},
+    prompt::confirm,
+    report::{collect as collect_report, write_report},
+    targets::Target,
     toolchain::{
-        InstallMode,
-        gcc::uninstall_gcc_toolchains,
+        ComponentStatus, HTTP_LOG_TARGET, InstallMode, configure as toolchain_configure,
+        gcc::{RISCV_GCC, XTENSA_GCC, gcc_arch},
         install as toolchain_install,
-        llvm::Llvm,
-        remove_dir,
-        rust::{XtensaRust, get_rustup_home},
+        manifest::{
+            MANIFEST_FILE, cache_key, disk_usage, installed_at, installed_host_triple,
+            installed_options, installed_targets, installed_version, modified_profiles,
+            record_profile, untracked_toolchains,
+        },
+        progress::DefaultProgressReporter,
+        prune_gcc,
+        rust::{RustupProfile, XtensaRust, get_rustup_home},
+        settings::{Settings, scope as settings_scope},
+        shared::{set_read_only, unlink_toolchain},
+        timings::{breakdown as timings_breakdown, to_json as timings_json},
+        uninstall as toolchain_uninstall, verify as toolchain_verify,
     },
     update::check_for_update,
+    vscode::write_settings as write_vscode_settings,
 };
-use log::info;
+use log::{debug, info, warn};
 use miette::Result;
-use std::{env, io::stdout};
+use serde_json::json;
+use std::{
+    collections::{BTreeSet, HashSet},
+    env,
+    fs::{remove_dir_all, remove_file},
+    io::{self, Write, stdout},
+    iter,
+    path::{Path, PathBuf},
+    process::exit,
+    sync::Arc,
+    time::SystemTime,
+};
+use strum::IntoEnumIterator;
+use tokio::signal;
 
 #[derive(Parser)]
 #[command(about, version)]
 struct Cli {
     #[command(subcommand)]
     subcommand: SubCommand,
+    /// Coloring of the logs and error messages.
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+    /// Prints errors to stderr as JSON objects, with their diagnostic code, message and fields, for CI and embedders.
+    #[arg(long, global = true)]
+    json: bool,
+    /// Prints only ASCII characters, prefixing the logs with tags like `[INFO]` instead of emoji and drawing the error messages without Unicode symbols, for consoles and log systems that can't render them.
+    #[arg(long, global = true, env = "ESPUP_NO_EMOJI", value_parser = BoolishValueParser::new())]
+    no_emoji: bool,
 }
 
 #[derive(Parser)]
 pub enum SubCommand {
+    /// Adds targets to an existing installation, only installing the components they are missing.
+    AddTarget(Box<AddTargetOpts>),
+    /// Prints a key that identifies the installed toolchains, useful for CI caching.
+    CacheKey(CacheKeyOpts),
     /// Generate completions for the given shell.
     Completions(CompletionsOpts),
+    /// Writes the export file of an existing installation, without downloading anything.
+    Configure(ConfigureOpts),
+    /// Prints the environment variables set by the export file, or, with `--diff`, how they change the current environment.
+    Env(EnvOpts),
     /// Installs Espressif Rust ecosystem.
     // We use a Box here to make clippy happy (see https://rust-lang.github.io/rust-clippy/master/index.html#large_enum_variant)
     Install(Box<InstallOpts>),
+    /// Bundles the system information, installation manifest and export file, with secrets redacted, into an archive for bug reports.
+    Report(ReportOpts),
+    /// Manages espup itself.
+    #[command(name = "self", subcommand)]
+    SelfCommand(SelfSubCommand),
+    /// Lists the supported targets, with their architecture and GCC toolchain.
+    Targets(TargetsOpts),
     /// Uninstalls Espressif Rust ecosystem.
     Uninstall(UninstallOpts),
     /// Updates Xtensa Rust toolchain.
-    Update(Box<InstallOpts>),
+    Update(Box<UpdateOpts>),
+    /// Verifies the installed Espressif Rust ecosystem.
+    Verify(VerifyOpts),
+}
+
+#[derive(Parser)]
+pub enum SelfSubCommand {
+    /// Uninstalls espup, the Espressif Rust ecosystem and every file espup created.
+    Uninstall(UninstallOpts),
 }
 
-/// Updates Xtensa Rust toolchain.
+/// Adds targets to an existing installation.
+///
+/// The installation is repeated for the installed and the new targets, pinned to the installed
+/// versions, so the installed components are reused and the export file covers every target.
+async fn add_target(mut args: AddTargetOpts) -> Result<()> {
+    initialize_logger(&log_filter(&args.install));
+
+    let toolchain_dir = toolchain_dir(
+        &args.install.name,
+        args.install.shared_install.as_deref(),
+        args.install.linked_toolchain_dir(),
+    )?;
+    let installed_targets = installed_targets(&toolchain_dir)?.ok_or_else(|| {
+        Error::MissingManifest(toolchain_dir.join(MANIFEST_FILE).display().to_string())
+    })?;
+    if args.new_targets.is_subset(&installed_targets) {
+        info!(
+            "Targets already installed: {}",
+            format_targets(&args.new_targets)
+        );
+        return Ok(());
+    }
+
+    pin_installed_versions(&mut args.install, &toolchain_dir)?;
+    args.install.targets = Some(
+        installed_targets
+            .union(&args.new_targets)
+            .copied()
+            .collect(),
+    );
+    install_ecosystem(args.install, InstallMode::Install).await
+}
+
+/// Pins the Xtensa Rust and GCC versions of an installation to the installed ones, unless they are
+/// selected, so the installed components are reused.
+fn pin_installed_versions(args: &mut InstallOpts, toolchain_dir: &Path) -> Result<()> {
+    if args.toolchain_version.is_none() && args.toolchain_url.is_none() {
+        args.toolchain_version = installed_version(toolchain_dir, "Xtensa Rust")?;
+    }
+    if args.crosstool_toolchain_version.is_none() {
+        args.crosstool_toolchain_version =
+            match installed_version(toolchain_dir, &format!("GCC ({XTENSA_GCC})"))? {
+                Some(version) => Some(version),
+                None => installed_version(toolchain_dir, &format!("GCC ({RISCV_GCC})"))?,
+            };
+    }
+    Ok(())
+}
+
+/// Prints the cache key of the installed toolchains.
+async fn print_cache_key(args: CacheKeyOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    let toolchain_dir = toolchain_dir(&args.name, None, None)?;
+    println!("{}", cache_key(&toolchain_dir)?);
+    Ok(())
+}
+
+/// Writes a diagnostic report for bug reports.
+async fn report(args: ReportOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    let toolchain_dir = toolchain_dir(&args.name, None, None)?;
+    let export_file = match args.export_file {
+        Some(export_file) => export_file,
+        None => default_export_file()?,
+    };
+    let output = match args.output {
+        Some(output) => output,
+        None => {
+            let timestamp = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            PathBuf::from(format!("espup-report-{timestamp}.zip"))
+        }
+    };
+    write_report(&output, &collect_report(&toolchain_dir, &export_file))?;
+    info!("Report written, with secrets redacted, to:");
+    println!("{}", output.display());
+    Ok(())
+}
+
+/// Prints the supported targets, the values accepted by `--targets`.
+async fn list_targets(args: TargetsOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    if args.format == "json" {
+        let targets: Vec<_> = Target::iter()
+            .map(|target| {
+                json!({
+                    "target": target.to_string(),
+                    "architecture": if target.is_xtensa() { "xtensa" } else { "riscv" },
+                    "gcc": gcc_arch(&target),
+                })
+            })
+            .collect();
+        println!("{}", json!(targets));
+    } else {
+        println!("{:10}  {:12}  GCC", "Target", "Architecture");
+        for target in Target::iter() {
+            let architecture = if target.is_xtensa() {
+                "Xtensa"
+            } else {
+                "RISC-V"
+            };
+            println!(
+                "{:10}  {architecture:12}  {}",
+                target.to_string(),
+                gcc_arch(&target)
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Writes the export file of an existing installation.
+async fn configure(args: ConfigureOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    info!(
+        "Configuring the installation in '{}'",
+        args.install_dir.display()
+    );
+    let export_file = toolchain_configure(&args.install_dir, args.export_file, args.force)?;
+    info!("Export file written to '{}'", export_file.display());
+    Ok(())
+}
+
+/// Prints the environment variables set by the export file, or how they change the current
+/// environment.
+async fn print_env(args: EnvOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    let export_file = match args.export_file {
+        Some(export_file) => export_file,
+        None => default_export_file()?,
+    };
+    let exports = read_exports(&export_file)?;
+    if !args.diff {
+        if args.format == "json" {
+            println!("{}", exports_to_json(&exports));
+        } else {
+            for (name, value) in exports {
+                println!("{name}={value}");
+            }
+        }
+        return Ok(());
+    }
+
+    let changes = diff_exports(&exports, |name| env::var(name).ok());
+    if args.format == "json" {
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|change| {
+                json!({
+                    "name": change.name,
+                    "value": change.value,
+                    "current": change.current,
+                    "change": change.kind.to_string(),
+                })
+            })
+            .collect();
+        println!("{}", json!(changes));
+    } else {
+        for change in changes {
+            match (change.kind, change.current) {
+                (EnvChangeKind::Added, _) if change.name == "PATH" => {
+                    println!("+ PATH: prepends '{}'", change.value)
+                }
+                (EnvChangeKind::Unchanged, _) if change.name == "PATH" => {
+                    println!("  PATH: already contains '{}'", change.value)
+                }
+                (EnvChangeKind::Added, _) => println!("+ {}={}", change.name, change.value),
+                (EnvChangeKind::Modified, Some(current)) => {
+                    println!("~ {}={} (currently '{current}')", change.name, change.value)
+                }
+                (_, _) => println!("  {}={}", change.name, change.value),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generates completions for the given shell.
 async fn completions(args: CompletionsOpts) -> Result<()> {
     initialize_logger(&args.log_level);
     check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
+    if args.print_path {
+        let directory = args.shell.default_directory()?;
+        println!("{}", directory.display());
+        if !directory.exists() {
+            warn!(
+                "'{}' doesn't exist yet, create it, or use '--install' to create it and write the completions file into it",
+                directory.display()
+            );
+        }
+        if let Some(hint) = args.shell.setup_hint(&directory, "espup") {
+            info!("{hint}");
+        }
+        return Ok(());
+    }
+
     info!("Generating completions for {} shell", args.shell);
 
-    args.shell
-        .generate(&mut Cli::command(), "espup", &mut stdout());
+    let output = if args.install {
+        Some(args.shell.default_directory()?)
+    } else {
+        args.output
+    };
+
+    if let Some(output) = output {
+        let path = args
+            .shell
+            .generate_to(&mut Cli::command(), "espup", &output)?;
+        info!("Completions written to '{}'", path.display());
+    } else {
+        args.shell
+            .generate(&mut Cli::command(), "espup", &mut stdout());
+    }
 
     info!("Completions successfully generated!");
 
@@ -53,41 +335,800 @@ async fn completions(args: CompletionsOpts) -> Result<()> {
 
 /// Installs or updates the Rust for ESP chips environment
 async fn install(args: InstallOpts, install_mode: InstallMode) -> Result<()> {
-    initialize_logger(&args.log_level);
+    initialize_logger(&log_filter(&args));
+    install_ecosystem(args, install_mode).await
+}
+
+/// Installs or updates the Rust for ESP chips environment, once the logger is initialized
+async fn install_ecosystem(mut args: InstallOpts, install_mode: InstallMode) -> Result<()> {
     check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
-    toolchain_install(args, install_mode).await?;
+    // Shared and throwaway installations are not installed into the home directory of root
+    if is_root() && !args.allow_root && args.shared_install.is_none() && !args.ephemeral {
+        warn!(
+            "espup is running as root: the toolchains are installed into '{}', where builds of other users won't find them",
+            get_home_dir()?.display()
+        );
+        match env::var("SUDO_USER") {
+            Ok(user) => warn!("Run espup as '{user}', without sudo, instead"),
+            Err(_) => warn!("Run espup as a normal user instead"),
+        }
+        warn!(
+            "Use '--allow-root' to install for root on purpose, or '--shared-install' to install for every user"
+        );
+    }
+
+    if args.auto_targets {
+        match detect_connected_target().await {
+            Ok(target) => {
+                info!("Installing the target of the connected board: {target}");
+                args.targets = Some(HashSet::from([target]));
+            }
+            Err(e) => warn!("{e}. Falling back to the default targets"),
+        }
+    }
+    let project_targets = detect_project_targets(&env::current_dir().map_err(Error::IoError)?);
+    match (&args.targets, project_targets) {
+        (None, Some(project_targets)) => {
+            info!(
+                "Installing the targets of the Cargo project in the current directory: {}",
+                format_targets(&project_targets)
+            );
+            args.targets = Some(project_targets);
+        }
+        (Some(targets), Some(project_targets)) => {
+            let missing: HashSet<Target> = project_targets.difference(targets).copied().collect();
+            if !missing.is_empty() {
+                warn!(
+                    "The Cargo project in the current directory builds for targets that are not being installed: {}",
+                    format_targets(&missing)
+                );
+            }
+        }
+        _ => {}
+    }
+    // Fail before installing anything if the profile can't be modified
+    let profile_shell = if args.modify_profile {
+        let shell = ProfileShell::detect()?;
+        // The default export file can only be sourced by POSIX shells on Unix
+        let own_export_file = cfg!(windows) || shell.export_shell() != ExportShell::Posix;
+        if own_export_file && !args.shells.contains(&shell.export_shell()) {
+            args.shells.push(shell.export_shell());
+        }
+        Some((shell, own_export_file))
+    } else {
+        None
+    };
+    let no_modify_path = args.no_modify_path || args.ephemeral;
+    let ephemeral = args.ephemeral;
+    let vscode = args.vscode.clone();
+    let toolchain_file = args.write_toolchain_file.clone();
+    let timings = args.timings.clone();
+    let rust_components = args.rust_components.clone();
+    let targets = args
+        .targets
+        .clone()
+        .unwrap_or_else(|| Target::iter().collect());
+    let std = args.std;
+    let name = args.name.clone();
+    let stable_version = args
+        .stable_version
+        .first()
+        .cloned()
+        .ok_or(Error::MissingStableVersion)?;
+    let summary = toolchain_install(
+        args.into(),
+        install_mode,
+        Arc::new(DefaultProgressReporter::default()),
+    )
+    .await?;
+    let toolchain = if targets.iter().any(|t| t.is_xtensa()) {
+        name
+    } else {
+        // The stable version that was installed, out of the candidates
+        summary
+            .components
+            .iter()
+            .find(|component| component.name == "RISC-V Rust target")
+            .map(|component| component.version.clone())
+            .unwrap_or(stable_version)
+    };
+    match timings.as_deref() {
+        Some("json") => println!(
+            "{}",
+            serde_json::to_string_pretty(&timings_json(&summary.timings, summary.elapsed))
+                .map_err(|_| Error::SerializeJson)?
+        ),
+        Some(_) => info!("{}", timings_breakdown(&summary.timings, summary.elapsed)),
+        None => debug!("{}", timings_breakdown(&summary.timings, summary.elapsed)),
+    }
+    if let Some(dir) = vscode {
+        // The std RISC-V targets are built from rust-src, with the nightly toolchain of the project
+        let vscode_toolchain = if targets.iter().any(|t| t.is_xtensa()) || !std {
+            Some(toolchain.as_str())
+        } else {
+            warn!(
+                "Not setting RUSTUP_TOOLCHAIN in the VS Code settings, the std RISC-V targets use the toolchain of the project"
+            );
+            None
+        };
+        write_vscode_settings(&dir, &summary.exports, vscode_toolchain, &targets, std)?;
+    }
+    if let Some(path) = toolchain_file {
+        if targets.iter().any(|t| t.is_xtensa()) {
+            write_toolchain_file(&path, &toolchain, &[], &[])?;
+        } else {
+            // rustup only installs the bare metal RISC-V targets, std applications build theirs from rust-src
+            let triples: BTreeSet<&str> = targets.iter().map(|t| t.triple(false)).collect();
+            let triples: Vec<&str> = triples.into_iter().collect();
+            let components: Vec<String> = iter::once("rust-src".to_string())
+                .chain(rust_components)
+                .collect();
+            write_toolchain_file(&path, &toolchain, &triples, &components)?;
+        }
+    }
+    let profile = match profile_shell {
+        Some((shell, own_export_file)) => {
+            let profile = shell.profile()?;
+            let export_file = if own_export_file {
+                summary
+                    .export_file
+                    .with_extension(shell.export_shell().extension())
+            } else {
+                summary.export_file.clone()
+            };
+            if add_to_profile(&profile, &export_file, shell)? {
+                info!(
+                    "Profile '{}' modified to source '{}'",
+                    profile.display(),
+                    export_file.display()
+                );
+            } else {
+                info!(
+                    "Profile '{}' already sources '{}'",
+                    profile.display(),
+                    export_file.display()
+                );
+            }
+            record_profile(&summary.toolchain_dir, &profile)?;
+            Some(profile)
+        }
+        None => None,
+    };
+    // The timings are the only output on stdout when printed as JSON, so they can be parsed
+    let mut out: Box<dyn Write> = if timings.as_deref() == Some("json") {
+        Box::new(io::stderr())
+    } else {
+        Box::new(io::stdout())
+    };
+    writeln!(out, "\n{}", summary.components_table()).map_err(Error::IoError)?;
+    print_post_install_msg(
+        &mut out,
+        &summary.export_file,
+        !no_modify_path,
+        profile.as_deref(),
+    )?;
+    if ephemeral && let Some(dir) = summary.export_file.parent() {
+        writeln!(out, "Ephemeral installation directory: {}", dir.display())
+            .map_err(Error::IoError)?;
+    }
     Ok(())
 }
 
+/// Formats a set of targets, sorted, for the logs.
+fn format_targets(targets: &HashSet<Target>) -> String {
+    Target::iter()
+        .filter(|target| targets.contains(target))
+        .map(|target| target.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 /// Uninstalls the Rust for ESP chips environment
 async fn uninstall(args: UninstallOpts) -> Result<()> {
     initialize_logger(&args.log_level);
     check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
 
+    let toolchain_dir = toolchain_dir(
+        &args.name,
+        args.shared_install.as_deref(),
+        args.linked_toolchain_dir(),
+    )?;
+    let mut removed: Vec<PathBuf> = [toolchain_dir]
+        .into_iter()
+        .filter(|path| path.exists())
+        .collect();
+    if args.deep_clean {
+        removed.extend(uninstall_leftovers(&args)?);
+    }
+    if !removed.is_empty() && !confirm_removal(&removed, args.yes)? {
+        info!("Uninstallation cancelled");
+        return Ok(());
+    }
+    uninstall_toolchains(args).await
+}
+
+/// Returns the directory of the `name` toolchains, in the shared prefix, the linked directory or
+/// the rustup toolchains directory.
+fn toolchain_dir(
+    name: &str,
+    shared_install: Option<&Path>,
+    linked_toolchain_dir: Option<PathBuf>,
+) -> Result<PathBuf> {
+    Ok(match (shared_install, linked_toolchain_dir) {
+        (Some(prefix), _) => prefix.join(name),
+        (None, Some(path)) => path,
+        (None, None) => get_rustup_home()?.join("toolchains").join(name),
+    })
+}
+
+/// Returns the leftovers of interrupted installations next to the toolchains to uninstall.
+///
+/// Only the rustup toolchains directory and shared prefixes are scanned, the parent of a linked
+/// toolchain may contain toolchains that espup didn't install.
+fn uninstall_leftovers(args: &UninstallOpts) -> Result<Vec<PathBuf>> {
+    let toolchain_dir = toolchain_dir(
+        &args.name,
+        args.shared_install.as_deref(),
+        args.linked_toolchain_dir(),
+    )?;
+    let scanned_dir = match (&args.shared_install, args.linked_toolchain_dir()) {
+        (Some(prefix), _) => prefix.clone(),
+        (None, Some(_)) => return Ok(Vec::new()),
+        (None, None) => get_rustup_home()?.join("toolchains"),
+    };
+    Ok(untracked_toolchains(&scanned_dir)
+        .into_iter()
+        .filter(|path| path != &toolchain_dir)
+        .collect())
+}
+
+/// Lists the paths that will be removed and asks the user to confirm it.
+fn confirm_removal(paths: &[PathBuf], yes: bool) -> Result<bool> {
+    println!("The following will be removed:");
+    for path in paths {
+        println!("  {}", path.display());
+    }
+    Ok(confirm("Do you want to continue?", yes)?)
+}
+
+/// Uninstalls the toolchains of the Rust for ESP chips environment
+async fn uninstall_toolchains(args: UninstallOpts) -> Result<()> {
     info!("Uninstalling the Espressif Rust ecosystem");
-    let toolchain_dir = get_rustup_home().join("toolchains").join(args.name);
+    let toolchain_dir = toolchain_dir(
+        &args.name,
+        args.shared_install.as_deref(),
+        args.linked_toolchain_dir(),
+    )?;
+    let leftovers = uninstall_leftovers(&args)?;
+    // The manifest is removed along with the toolchains
+    let profiles = modified_profiles(&toolchain_dir).unwrap_or_default();
+    let mut reclaimed = disk_usage(&toolchain_dir);
+    let mut failures = Vec::new();
+    if args.shared_install.is_some() {
+        warn!(
+            "Uninstalling the shared installation in '{}', this affects every user of the machine",
+            toolchain_dir.display()
+        );
+        if toolchain_dir.exists() {
+            set_read_only(&toolchain_dir, false)?;
+        }
+    }
+    if args.shared_install.is_some() || args.linked_toolchain_dir().is_some() {
+        let unlinked = unlink_toolchain(&args.name);
+        keep_going(unlinked, "Toolchain link", args.keep_going, &mut failures)?;
+    }
 
     if toolchain_dir.exists() {
-        Llvm::uninstall(&toolchain_dir).await?;
-
-        uninstall_gcc_toolchains(&toolchain_dir, args.crosstool_toolchain_version).await?;
+        failures.extend(
+            toolchain_uninstall(
+                &toolchain_dir,
+                args.crosstool_toolchain_version.clone(),
+                args.keep_going,
+                Settings::default(),
+            )
+            .await?,
+        );
+        let removed = remove_dir_all(&toolchain_dir)
+            .map_err(|_| Error::RemoveDirectory(toolchain_dir.display().to_string()));
+        keep_going(
+            removed,
+            "Toolchain directory",
+            args.keep_going,
+            &mut failures,
+        )?;
+    }
+    // Files that couldn't be removed with `--keep-going` are still there
+    reclaimed = reclaimed.saturating_sub(disk_usage(&toolchain_dir));
 
-        XtensaRust::uninstall(&toolchain_dir).await?;
+    if args.deep_clean {
+        for leftover in leftovers {
+            let usage = disk_usage(&leftover);
+            let removed = remove_dir_all(&leftover)
+                .map_err(|_| Error::RemoveDirectory(leftover.display().to_string()));
+            if keep_going(removed, "Leftover", args.keep_going, &mut failures)?.is_some() {
+                reclaimed += usage;
+                info!("Removed '{}'", leftover.display());
+            }
+        }
+    } else if !leftovers.is_empty() {
+        for leftover in &leftovers {
+            warn!(
+                "'{}' ({} MB) was left behind by an interrupted installation",
+                leftover.display(),
+                disk_usage(leftover) / 1_000_000
+            );
+        }
+        warn!("Use '--deep-clean' to remove the leftovers");
+    }
+    info!("Reclaimed {} MB of disk space", reclaimed / 1_000_000);
 
-        remove_dir(&toolchain_dir).await?;
+    let export_files = export_file_variants(&get_export_file(args.export_file.clone())?);
+    // Profiles that no longer exist have nothing to remove
+    for profile in profiles {
+        let removed = remove_from_profile(&profile, &export_files);
+        if keep_going(removed, "Shell profile", args.keep_going, &mut failures)? == Some(true) {
+            info!(
+                "Profile '{}' no longer sources the export file",
+                profile.display()
+            );
+        }
     }
 
+    if !failures.is_empty() {
+        let failures: Vec<String> = failures
+            .iter()
+            .map(|(name, e)| format!("- {name}: {e}"))
+            .collect();
+        return Err(Error::PartialUninstall {
+            failures: failures.join("\n"),
+        }
+        .into());
+    }
     info!("Uninstallation successfully completed!");
     Ok(())
 }
 
+/// Returns the result of a step of the uninstallation, or, with `--keep-going`, records its error
+/// in `failures` and carries on.
+fn keep_going<T>(
+    result: Result<T, Error>,
+    name: &str,
+    keep_going: bool,
+    failures: &mut Vec<(String, Error)>,
+) -> Result<Option<T>, Error> {
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(e) if keep_going => {
+            warn!("{name}: {e}");
+            failures.push((name.to_string(), e));
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Uninstalls the Rust for ESP chips environment, espup itself and every file espup created
+async fn self_uninstall(args: UninstallOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+
+    let espup_bin = env::current_exe().map_err(Error::IoError)?;
+    let toolchain_dir = toolchain_dir(
+        &args.name,
+        args.shared_install.as_deref(),
+        args.linked_toolchain_dir(),
+    )?;
+    let mut paths = export_file_variants(&get_export_file(args.export_file.clone())?);
+    paths.push(get_espup_dir()?);
+    // Cache of the update checks
+    if let Some(base_dirs) = BaseDirs::new() {
+        paths.push(
+            base_dirs
+                .cache_dir()
+                .join("update-informer-rs")
+                .join(format!("crates-{}", env!("CARGO_PKG_NAME"))),
+        );
+    }
+    paths.retain(|path| path.exists());
+    paths.sort();
+    paths.dedup();
+
+    let leftovers = if args.deep_clean {
+        uninstall_leftovers(&args)?
+    } else {
+        Vec::new()
+    };
+    let removed: Vec<PathBuf> = [toolchain_dir]
+        .into_iter()
+        .filter(|path| path.exists())
+        .chain(leftovers)
+        .chain(paths.iter().cloned())
+        .chain([espup_bin.clone()])
+        .collect();
+    if !confirm_removal(&removed, args.yes)? {
+        info!("Uninstallation cancelled");
+        return Ok(());
+    }
+
+    uninstall_toolchains(args).await?;
+    for path in paths {
+        if path.is_dir() {
+            remove_dir_all(&path)
+                .map_err(|_| Error::RemoveDirectory(path.display().to_string()))?;
+        } else {
+            remove_file(&path).map_err(|_| Error::RemoveDirectory(path.display().to_string()))?;
+        }
+        info!("Removed '{}'", path.display());
+    }
+    remove_self(&espup_bin)?;
+    info!("Removed '{}'", espup_bin.display());
+
+    Ok(())
+}
+
+/// Removes the espup binary.
+///
+/// A running executable can't be deleted on Windows, so the deletion is scheduled to happen once
+/// espup exits.
+fn remove_self(espup_bin: &Path) -> Result<()> {
+    #[cfg(unix)]
+    remove_file(espup_bin).map_err(|_| Error::RemoveDirectory(espup_bin.display().to_string()))?;
+    #[cfg(windows)]
+    std::process::Command::new("cmd")
+        .args([
+            "/C",
+            "ping",
+            "-n",
+            "3",
+            "127.0.0.1",
+            ">",
+            "NUL",
+            "&",
+            "del",
+            "/F",
+            "/Q",
+        ])
+        .arg(espup_bin)
+        .spawn()
+        .map_err(Error::IoError)?;
+    Ok(())
+}
+
+/// Exit code used when the process is interrupted with Ctrl-C.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Returns the filter of the logs, which also enables the HTTP exchange logs with `--trace-http`.
+fn log_filter(args: &InstallOpts) -> String {
+    if args.trace_http {
+        format!("{},{HTTP_LOG_TARGET}=trace", args.log_level)
+    } else {
+        args.log_level.clone()
+    }
+}
+
+/// Exit code used by `update --check` when a newer version is available.
+const UPDATE_AVAILABLE_EXIT_CODE: i32 = 65;
+
+/// Exit code used by `update --check --max-age` when the installation is older than the maximum age.
+const OUTDATED_EXIT_CODE: i32 = 66;
+
+/// Number of seconds in a day.
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Updates the Rust for ESP chips environment, or checks whether an update is available
+async fn update(args: UpdateOpts) -> Result<()> {
+    if !args.check {
+        let toolchain_dir = toolchain_dir(
+            &args.install.name,
+            args.install.shared_install.as_deref(),
+            args.install.linked_toolchain_dir(),
+        )?;
+        install(args.install, InstallMode::Update).await?;
+        if args.prune {
+            let targets = installed_targets(&toolchain_dir)?.ok_or_else(|| {
+                Error::MissingManifest(toolchain_dir.join(MANIFEST_FILE).display().to_string())
+            })?;
+            let reclaimed = prune_gcc(&toolchain_dir, &targets).await?;
+            info!("Reclaimed {} MB of disk space", reclaimed / 1_000_000);
+        }
+        return Ok(());
+    }
+
+    initialize_logger(&log_filter(&args.install));
+    let toolchain_dir = toolchain_dir(
+        &args.install.name,
+        args.install.shared_install.as_deref(),
+        args.install.linked_toolchain_dir(),
+    )?;
+    let installed = installed_version(&toolchain_dir, "Xtensa Rust")?
+        .ok_or_else(|| Error::MissingToolchain(toolchain_dir.display().to_string()))?;
+    let settings = Settings {
+        disable_http_timeouts: args.install.disable_timeouts,
+        proxy: args.install.proxy.clone(),
+        refresh: args.install.refresh,
+        version_api_fallbacks: args.install.version_api_url.clone(),
+        ..Default::default()
+    };
+    let latest = settings_scope(settings.clone(), XtensaRust::get_latest_version()).await?;
+    let update_available = installed != latest;
+    let age_days = installed_at(&toolchain_dir)?.map(|installed_at| {
+        SystemTime::now()
+            .duration_since(installed_at)
+            .unwrap_or_default()
+            .as_secs()
+            / SECONDS_PER_DAY
+    });
+    let outdated = match (args.max_age, age_days) {
+        (Some(max_age), Some(age_days)) => age_days > max_age,
+        (Some(_), None) => {
+            warn!("The installation date is unknown, reinstall the toolchains to record it");
+            false
+        }
+        (None, _) => false,
+    };
+    let release_notes = match &args.since {
+        Some(since) => {
+            let since = since.as_deref().unwrap_or(&installed);
+            match settings_scope(settings, XtensaRust::release_notes(since, &latest)).await {
+                Ok(release_notes) => Some(release_notes),
+                Err(e) => {
+                    warn!("Failed to fetch the release notes: {e}");
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+
+    if args.format == "json" {
+        println!(
+            "{}",
+            json!({
+                "installed": installed,
+                "latest": latest,
+                "update_available": update_available,
+                "age_days": age_days,
+                "outdated": outdated,
+                "release_notes": release_notes.as_ref().map(|release_notes| {
+                    release_notes
+                        .iter()
+                        .map(|notes| json!({ "version": notes.version, "notes": notes.notes }))
+                        .collect::<Vec<_>>()
+                }),
+            })
+        );
+    } else {
+        if update_available {
+            println!("Xtensa Rust {latest} is available (installed: {installed})");
+        } else {
+            println!("Xtensa Rust {installed} is up to date");
+        }
+        for notes in release_notes.iter().flatten() {
+            println!("\n## Xtensa Rust {}\n", notes.version);
+            if !notes.notes.is_empty() {
+                println!("{}", notes.notes);
+            }
+        }
+    }
+    if outdated {
+        warn!(
+            "The installation is {} days old, older than the maximum age of {} days. Please, update it",
+            age_days.unwrap_or_default(),
+            args.max_age.unwrap_or_default()
+        );
+    }
+
+    if update_available {
+        exit(UPDATE_AVAILABLE_EXIT_CODE);
+    }
+    if outdated {
+        exit(OUTDATED_EXIT_CODE);
+    }
+    Ok(())
+}
+
+/// Verifies the Rust for ESP chips environment
+async fn verify(args: VerifyOpts) -> Result<()> {
+    initialize_logger(&args.log_level);
+    check_for_update(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    if !args.repair {
+        return verify_installation(&args).await;
+    }
+    match verify_installation(&args).await {
+        Err(report)
+            if matches!(
+                report.downcast_ref::<Error>(),
+                Some(Error::CorruptInstallation(_) | Error::MissingComponents(_))
+            ) =>
+        {
+            warn!("Repairing the installation: {report}");
+            repair(&args.name).await?;
+            verify_installation(&args).await
+        }
+        result => result,
+    }
+}
+
+/// Verifies the components of an installation, printing their status, and fails when any is
+/// corrupt, modified or missing.
+async fn verify_installation(args: &VerifyOpts) -> Result<()> {
+    let verification = toolchain_verify(&args.name, &args.components).await?;
+    if let Some(summary) = &verification.summary {
+        info!("Toolchains {summary}");
+    }
+
+    if args.format == "json" {
+        let statuses: Vec<serde_json::Value> = verification
+            .components
+            .iter()
+            .map(|(name, status)| match status {
+                ComponentStatus::Ok => json!({ "name": name, "status": "ok" }),
+                ComponentStatus::Corrupt(binaries) => json!({
+                    "name": name,
+                    "status": "corrupt",
+                    "broken": binaries,
+                }),
+                ComponentStatus::Modified(changes) => json!({
+                    "name": name,
+                    "status": "modified",
+                    "changes": changes,
+                }),
+            })
+            .chain(
+                verification
+                    .missing
+                    .iter()
+                    .map(|name| json!({ "name": name, "status": "missing" })),
+            )
+            .collect();
+        println!("{}", json!(statuses));
+    }
+
+    for (name, status) in &verification.components {
+        match status {
+            ComponentStatus::Ok => info!("{name}: OK"),
+            ComponentStatus::Corrupt(binaries) => {
+                for binary in binaries {
+                    warn!(
+                        "{name}: '{}' is missing or not executable",
+                        binary.display()
+                    );
+                }
+                warn!("{name}: corrupt");
+            }
+            ComponentStatus::Modified(changes) => {
+                for change in changes {
+                    warn!("{change}");
+                }
+                warn!("{name}: modified");
+            }
+        }
+    }
+    for name in &verification.missing {
+        warn!("{name}: not installed");
+    }
+
+    if let Some(error) = verification.error() {
+        return Err(error.into());
+    }
+    info!("Verification successfully completed!");
+    Ok(())
+}
+
+/// Reinstalls the `name` toolchains over their failing components, with the options, versions and
+/// targets recorded in the installation manifest.
+async fn repair(name: &str) -> Result<()> {
+    let toolchain_dir = toolchain_dir(name, None, None)?;
+    let options = installed_options(&toolchain_dir)?
+        .ok_or_else(|| Error::MissingOptions(toolchain_dir.display().to_string()))?;
+    let mut args = InstallOpts::parse_from(["install", "--name", name]);
+    apply_installed_options(&mut args, &options);
+    if args.cross_host {
+        args.default_host = installed_host_triple(&toolchain_dir)?.map(|host| host.to_string());
+    }
+    pin_installed_versions(&mut args, &toolchain_dir)?;
+    args.targets = installed_targets(&toolchain_dir)?;
+    if let Some(version) = installed_version(&toolchain_dir, "RISC-V Rust target")? {
+        args.stable_version = vec![version];
+    }
+    // The failing components are modified or corrupt, so they are never reused
+    args.force = true;
+    install_ecosystem(args, InstallMode::Install).await
+}
+
+/// Sets the options of an installation, as recorded in its manifest.
+fn apply_installed_options(args: &mut InstallOpts, options: &serde_json::Value) {
+    let flag = |name: &str| options[name].as_bool().unwrap_or_default();
+    let string = |name: &str| options[name].as_str().map(str::to_string);
+    let strings = |name: &str| -> Vec<String> {
+        options[name]
+            .as_array()
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|value| value.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    if let Some(algo) = string("checksum_algo").and_then(|algo| algo.parse().ok()) {
+        args.checksum_algo = algo;
+    }
+    args.cross_host = flag("cross_host");
+    args.esp_riscv_gcc = flag("esp_riscv_gcc");
+    args.export_file = string("export_file").map(PathBuf::from);
+    args.extended_llvm = flag("extended_llvm");
+    args.extra_artifacts = strings("extra_artifacts");
+    // The recorded link is the resolved one of `--install-dir` too
+    args.install_dir = None;
+    args.link_toolchain = string("link_toolchain").map(PathBuf::from);
+    args.llvm_version = string("llvm_version");
+    args.no_gcc = flag("no_gcc");
+    args.rust_components = strings("rust_components");
+    if let Some(profile) =
+        string("rustup_profile").and_then(|profile| RustupProfile::from_str(&profile, true).ok())
+    {
+        args.rustup_profile = profile;
+    }
+    args.shared_install = string("shared_install").map(PathBuf::from);
+    args.skip_llvm = flag("skip_llvm");
+    args.skip_version_parse = flag("skip_version_parse");
+    args.std = flag("std");
+    args.toolchain_checksum = string("toolchain_checksum");
+    args.toolchain_url = string("toolchain_url");
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    match Cli::parse().subcommand {
+    let cli = Cli::parse();
+    set_color(cli.color);
+    set_unicode(!cli.no_emoji);
+    let result = tokio::select! {
+        result = run(cli.subcommand) => result,
+        // The installations remove their partial directories when they are dropped
+        _ = signal::ctrl_c() => {
+            warn!("Interrupted");
+            exit(INTERRUPTED_EXIT_CODE);
+        }
+    };
+    match result {
+        Err(report) if cli.json => {
+            print_json_error(&report);
+            exit(1);
+        }
+        result => result,
+    }
+}
+
+/// Prints an error to stderr as a JSON object, with its diagnostic code, message and fields.
+fn print_json_error(report: &miette::Report) {
+    let fields = report
+        .downcast_ref::<Error>()
+        .map_or_else(|| json!({}), Error::fields);
+    eprintln!(
+        "{}",
+        json!({
+            "code": report.code().map(|code| code.to_string()),
+            "message": report.to_string(),
+            "fields": fields,
+        })
+    );
+}
+
+async fn run(subcommand: SubCommand) -> Result<()> {
+    match subcommand {
+        SubCommand::AddTarget(args) => add_target(*args).await,
+        SubCommand::CacheKey(args) => print_cache_key(args).await,
         SubCommand::Completions(args) => completions(args).await,
+        SubCommand::Configure(args) => configure(args).await,
+        SubCommand::Env(args) => print_env(args).await,
         SubCommand::Install(args) => install(*args, InstallMode::Install).await,
-        SubCommand::Update(args) => install(*args, InstallMode::Update).await,
+        SubCommand::Report(args) => report(args).await,
+        SubCommand::SelfCommand(SelfSubCommand::Uninstall(args)) => self_uninstall(args).await,
+        SubCommand::Targets(args) => list_targets(args).await,
+        SubCommand::Update(args) => update(*args).await,
         SubCommand::Uninstall(args) => uninstall(args).await,
+        SubCommand::Verify(args) => verify(args).await,
     }
 }
@@ -0,0 +1,211 @@
+//! Detection of the targets a Cargo project builds for.
+
+use crate::{error::Error, targets::Target};
+use log::{debug, info};
+use std::{
+    collections::HashSet,
+    fs::{copy, read_to_string, write},
+    path::{Path, PathBuf},
+};
+use strum::IntoEnumIterator;
+
+/// Cargo configuration files, in the order Cargo reads them.
+const CARGO_CONFIG_FILES: [&str; 2] = [".cargo/config.toml", ".cargo/config"];
+/// File rustup reads the toolchain of a project from.
+const TOOLCHAIN_FILE: &str = "rust-toolchain.toml";
+
+/// Returns the targets the Cargo project containing `dir` builds for, as configured by the
+/// `build.target` key of its `.cargo/config.toml`.
+///
+/// Returns `None` when there is no Cargo configuration or it doesn't configure any ESP target.
+pub fn detect_targets(dir: &Path) -> Option<HashSet<Target>> {
+    for config_file in dir
+        .ancestors()
+        .flat_map(|dir| CARGO_CONFIG_FILES.iter().map(move |file| dir.join(file)))
+    {
+        let Ok(contents) = read_to_string(&config_file) else {
+            continue;
+        };
+        let triples = parse_build_targets(&contents);
+        if triples.is_empty() {
+            continue;
+        }
+        debug!("Build targets of '{}': {triples:?}", config_file.display());
+        let targets: HashSet<Target> = Target::iter()
+            .filter(|target| {
+                triples
+                    .iter()
+                    .any(|triple| triple == target.triple(false) || triple == target.triple(true))
+            })
+            .collect();
+        return (!targets.is_empty()).then_some(targets);
+    }
+    None
+}
+
+/// Returns the triples of the `build.target` key, which is either a string or an array of strings.
+fn parse_build_targets(contents: &str) -> Vec<String> {
+    let config: toml::Table = match contents.parse() {
+        Ok(config) => config,
+        Err(e) => {
+            debug!("Ignoring invalid Cargo configuration: {e}");
+            return Vec::new();
+        }
+    };
+    match config.get("build").and_then(|build| build.get("target")) {
+        Some(toml::Value::String(target)) => vec![target.clone()],
+        Some(toml::Value::Array(targets)) => targets
+            .iter()
+            .filter_map(toml::Value::as_str)
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Writes a `rust-toolchain.toml` file pinning a project to the `channel` toolchain, with the
+/// given targets and components, into `path`, or into the `path` directory.
+///
+/// Targets and components can only be listed for toolchains managed by rustup, the Xtensa Rust
+/// toolchain already includes them. The original file, if any, is backed up as
+/// `rust-toolchain.toml.bak`.
+pub fn write_toolchain_file(
+    path: &Path,
+    channel: &str,
+    targets: &[&str],
+    components: &[String],
+) -> Result<PathBuf, Error> {
+    let toolchain_file = if path.is_dir() {
+        path.join(TOOLCHAIN_FILE)
+    } else {
+        path.to_path_buf()
+    };
+    if toolchain_file.exists() {
+        let mut backup_path = toolchain_file.clone().into_os_string();
+        backup_path.push(".bak");
+        debug!("Backing up '{}'", toolchain_file.display());
+        copy(&toolchain_file, backup_path)?;
+    }
+
+    let list = |items: &[&str]| {
+        items
+            .iter()
+            .map(|item| format!("\"{item}\""))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let mut contents = format!("[toolchain]\nchannel = \"{channel}\"\n");
+    if !components.is_empty() {
+        let components: Vec<&str> = components.iter().map(String::as_str).collect();
+        contents.push_str(&format!("components = [{}]\n", list(&components)));
+    }
+    if !targets.is_empty() {
+        contents.push_str(&format!("targets = [{}]\n", list(targets)));
+    }
+    write(&toolchain_file, contents)?;
+    info!("Toolchain file written to '{}'", toolchain_file.display());
+
+    Ok(toolchain_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        project::{detect_targets, write_toolchain_file},
+        targets::Target,
+    };
+    use std::fs::{create_dir_all, read_to_string, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_detect_targets() {
+        let temp_dir = TempDir::new().unwrap();
+        let project_dir = temp_dir.path().join("project");
+        let src_dir = project_dir.join("src");
+        create_dir_all(&src_dir).unwrap();
+        create_dir_all(project_dir.join(".cargo")).unwrap();
+        assert_eq!(detect_targets(&src_dir), None);
+
+        let config_file = project_dir.join(".cargo").join("config.toml");
+        write(
+            &config_file,
+            "[target.xtensa-esp32s3-none-elf]\nrunner = \"espflash flash --monitor\"\n\n[build]\ntarget = \"xtensa-esp32s3-none-elf\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            detect_targets(&src_dir),
+            Some([Target::ESP32S3].into_iter().collect())
+        );
+
+        write(
+            &config_file,
+            "[build]\ntarget = [\"xtensa-esp32-espidf\", \"riscv32imafc-unknown-none-elf\"]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            detect_targets(&project_dir),
+            Some([Target::ESP32, Target::ESP32P4].into_iter().collect())
+        );
+
+        // Multi-line arrays with comments
+        write(
+            &config_file,
+            "[build]\ntarget = [\n    # Main core\n    \"xtensa-esp32s3-none-elf\",\n    \"riscv32imc-unknown-none-elf\", # ULP\n]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            detect_targets(&project_dir),
+            Some(
+                [Target::ESP32S3, Target::ESP32C2, Target::ESP32C3]
+                    .into_iter()
+                    .collect()
+            )
+        );
+
+        // Dotted keys
+        write(&config_file, "build.target = \"riscv32imafc-esp-espidf\"\n").unwrap();
+        assert_eq!(
+            detect_targets(&project_dir),
+            Some([Target::ESP32P4].into_iter().collect())
+        );
+
+        // Not an ESP target
+        write(
+            &config_file,
+            "[build]\ntarget = \"thumbv7em-none-eabihf\"\n",
+        )
+        .unwrap();
+        assert_eq!(detect_targets(&project_dir), None);
+    }
+
+    #[test]
+    fn test_write_toolchain_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let toolchain_file = write_toolchain_file(temp_dir.path(), "esp", &[], &[]).unwrap();
+        assert_eq!(toolchain_file, temp_dir.path().join("rust-toolchain.toml"));
+        assert_eq!(
+            read_to_string(&toolchain_file).unwrap(),
+            "[toolchain]\nchannel = \"esp\"\n"
+        );
+
+        let components = ["rust-src".to_string(), "clippy".to_string()];
+        write_toolchain_file(
+            &toolchain_file,
+            "stable",
+            &[
+                "riscv32imac-unknown-none-elf",
+                "riscv32imc-unknown-none-elf",
+            ],
+            &components,
+        )
+        .unwrap();
+        assert_eq!(
+            read_to_string(&toolchain_file).unwrap(),
+            "[toolchain]\nchannel = \"stable\"\ncomponents = [\"rust-src\", \"clippy\"]\ntargets = [\"riscv32imac-unknown-none-elf\", \"riscv32imc-unknown-none-elf\"]\n"
+        );
+        assert_eq!(
+            read_to_string(temp_dir.path().join("rust-toolchain.toml.bak")).unwrap(),
+            "[toolchain]\nchannel = \"esp\"\n"
+        );
+    }
+}
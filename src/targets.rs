@@ -41,27 +41,58 @@ impl Target {
     pub fn is_xtensa(&self) -> bool {
         matches!(self, Target::ESP32 | Target::ESP32S2 | Target::ESP32S3)
     }
+
+    /// Returns the Rust target triple of the chip, for `std` or `no_std` applications.
+    pub fn triple(&self, std: bool) -> &'static str {
+        match (self, std) {
+            (Target::ESP32, false) => "xtensa-esp32-none-elf",
+            (Target::ESP32, true) => "xtensa-esp32-espidf",
+            (Target::ESP32S2, false) => "xtensa-esp32s2-none-elf",
+            (Target::ESP32S2, true) => "xtensa-esp32s2-espidf",
+            (Target::ESP32S3, false) => "xtensa-esp32s3-none-elf",
+            (Target::ESP32S3, true) => "xtensa-esp32s3-espidf",
+            (Target::ESP32C2 | Target::ESP32C3, false) => "riscv32imc-unknown-none-elf",
+            (Target::ESP32C2 | Target::ESP32C3, true) => "riscv32imc-esp-espidf",
+            (Target::ESP32C5 | Target::ESP32C6 | Target::ESP32C61 | Target::ESP32H2, false) => {
+                "riscv32imac-unknown-none-elf"
+            }
+            (Target::ESP32C5 | Target::ESP32C6 | Target::ESP32C61 | Target::ESP32H2, true) => {
+                "riscv32imac-esp-espidf"
+            }
+            (Target::ESP32P4, false) => "riscv32imafc-unknown-none-elf",
+            (Target::ESP32P4, true) => "riscv32imafc-esp-espidf",
+        }
+    }
 }
 
 /// Returns a vector of Chips from a comma or space separated string.
+///
+/// Targets prefixed with `-` are removed from the set, tokens are processed in order, so
+/// `all,-esp32` yields every target except ESP32.
 pub fn parse_targets(targets_str: &str) -> Result<HashSet<Target>, Error> {
     debug!("Parsing targets: {targets_str}");
 
     let targets_str = targets_str.to_lowercase();
     let targets_str = targets_str.trim();
 
-    let targets: HashSet<Target> = if targets_str.contains("all") {
-        Target::iter().collect()
-    } else {
-        let mut targets = HashSet::new();
-        for target in targets_str.split([',', ' ']) {
+    let mut targets = HashSet::new();
+    for token in targets_str.split([',', ' ']).filter(|t| !t.is_empty()) {
+        if token == "all" {
+            targets.extend(Target::iter());
+        } else if let Some(target) = token.strip_prefix('-') {
+            targets.remove(
+                &Target::from_str(target).map_err(|_| Error::UnsupportedTarget(target.into()))?,
+            );
+        } else {
             targets.insert(
-                Target::from_str(target).map_err(|_| Error::UnsupportedTarget(target.into()))?,
+                Target::from_str(token).map_err(|_| Error::UnsupportedTarget(token.into()))?,
             );
         }
+    }
 
-        targets
-    };
+    if targets.is_empty() {
+        return Err(Error::NoTargets(targets_str.to_string()));
+    }
 
     debug!("Parsed targets: {targets:?}");
     Ok(targets)
@@ -71,6 +102,7 @@ pub fn parse_targets(targets_str: &str) -> Result<HashSet<Target>, Error> {
 mod tests {
     use crate::targets::{Target, parse_targets};
     use std::collections::HashSet;
+    use strum::IntoEnumIterator;
 
     #[test]
     #[allow(unused_variables)]
@@ -104,4 +136,50 @@ mod tests {
         .collect();
         assert!(matches!(parse_targets("all"), Ok(targets)));
     }
+
+    #[test]
+    fn test_target_architecture() {
+        for target in [Target::ESP32C6, Target::ESP32H2, Target::ESP32P4] {
+            assert!(target.is_riscv());
+            assert!(!target.is_xtensa());
+        }
+        assert_eq!(
+            parse_targets("esp32c6,esp32h2,esp32p4").unwrap(),
+            [Target::ESP32C6, Target::ESP32H2, Target::ESP32P4]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(
+            Target::ESP32C6.triple(false),
+            "riscv32imac-unknown-none-elf"
+        );
+        assert_eq!(Target::ESP32H2.triple(true), "riscv32imac-esp-espidf");
+        assert_eq!(
+            Target::ESP32P4.triple(false),
+            "riscv32imafc-unknown-none-elf"
+        );
+        assert_eq!(Target::ESP32P4.triple(true), "riscv32imafc-esp-espidf");
+    }
+
+    #[test]
+    fn test_parse_targets_subtraction() {
+        let all_but_esp32: HashSet<Target> =
+            Target::iter().filter(|t| t != &Target::ESP32).collect();
+        assert_eq!(parse_targets("all,-esp32").unwrap(), all_but_esp32);
+        let xtensa_less: HashSet<Target> = Target::iter()
+            .filter(|t| t != &Target::ESP32 && t != &Target::ESP32S2)
+            .collect();
+        assert_eq!(parse_targets("all,-esp32,-esp32s2").unwrap(), xtensa_less);
+        // Tokens are processed in order
+        assert_eq!(
+            parse_targets("esp32,esp32c3,-esp32,esp32s3").unwrap(),
+            [Target::ESP32C3, Target::ESP32S3].into_iter().collect()
+        );
+        assert_eq!(
+            parse_targets("esp32c3 -esp32c3 esp32c3").unwrap(),
+            [Target::ESP32C3].into_iter().collect()
+        );
+        assert!(parse_targets("esp32,-esp32").is_err());
+        assert!(parse_targets("all,-esp33").is_err());
+    }
 }
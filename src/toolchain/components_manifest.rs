@@ -0,0 +1,245 @@
+//! Components manifest, to download the toolchains from approved locations.
+//!
+//! The manifest is a JSON document mapping the upstream URL of every approved asset to the
+//! location it should be downloaded from and, optionally, its SHA-256, SHA-1 or BLAKE3 checksum,
+//! named after its algorithm:
+//!
+//! ```json
+//! {
+//!   "xtensa_rust_version": "1.88.0.0",
+//!   "assets": {
+//!     "https://github.com/esp-rs/rust-build/releases/download/v1.88.0.0/rust-1.88.0.0-x86_64-unknown-linux-gnu.tar.xz": {
+//!       "url": "https://mirror.example.com/rust-1.88.0.0-x86_64-unknown-linux-gnu.tar.xz",
+//!       "sha256": "..."
+//!     }
+//!   }
+//! }
+//! ```
+
+use crate::{
+    error::Error,
+    toolchain::{
+        build_proxy_async_client, manifest::ChecksumAlgo, settings, trace_request, trace_response,
+    },
+};
+use log::debug;
+use serde_json::Value;
+use std::{collections::HashMap, fs::read_to_string};
+use strum::IntoEnumIterator;
+
+/// Location and checksum of an approved asset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Asset {
+    /// URL to download the asset from.
+    pub url: String,
+    /// Expected checksum of the asset, and the algorithm it is computed with.
+    pub checksum: Option<(ChecksumAlgo, String)>,
+}
+
+/// Approved Xtensa Rust version and assets.
+#[derive(Debug, Clone, Default)]
+pub struct ComponentsManifest {
+    /// Xtensa Rust version to install when none is provided.
+    pub xtensa_rust_version: Option<String>,
+    /// Approved assets, indexed by their upstream URL.
+    pub assets: HashMap<String, Asset>,
+}
+
+impl ComponentsManifest {
+    /// Parses a components manifest.
+    pub fn parse(contents: &str) -> Result<Self, Error> {
+        let invalid = |reason: &str| Error::InvalidComponentsManifest(reason.to_string());
+        let json: Value = serde_json::from_str(contents).map_err(|e| invalid(&e.to_string()))?;
+        let xtensa_rust_version = match &json["xtensa_rust_version"] {
+            Value::Null => None,
+            Value::String(version) => Some(version.clone()),
+            _ => return Err(invalid("'xtensa_rust_version' must be a string")),
+        };
+        let mut assets = HashMap::new();
+        for (upstream_url, asset) in json["assets"]
+            .as_object()
+            .ok_or_else(|| invalid("'assets' must be an object"))?
+        {
+            let url = asset["url"]
+                .as_str()
+                .ok_or_else(|| invalid(&format!("missing 'url' for '{upstream_url}'")))?;
+            let checksum = ChecksumAlgo::iter().find_map(|algo| {
+                let checksum = asset[algo.to_string()].as_str()?;
+                Some((algo, checksum.to_lowercase()))
+            });
+            assets.insert(
+                upstream_url.clone(),
+                Asset {
+                    url: url.to_string(),
+                    checksum,
+                },
+            );
+        }
+
+        Ok(Self {
+            xtensa_rust_version,
+            assets,
+        })
+    }
+
+    /// Loads a components manifest from a URL or a local path.
+    pub async fn load(location: &str) -> Result<Self, Error> {
+        debug!("Loading components manifest from '{location}'");
+        let contents = if location.starts_with("http://") || location.starts_with("https://") {
+            let client = build_proxy_async_client()?;
+            let request = client.get(location).build()?;
+            trace_request(request.method(), request.url(), request.headers());
+            let response = client.execute(request).await?;
+            trace_response(response.url(), response.status(), response.headers());
+            if !response.status().is_success() {
+                return Err(Error::HttpError(response.status().to_string()));
+            }
+            response.text().await?
+        } else {
+            read_to_string(location)?
+        };
+        Self::parse(&contents)
+    }
+
+    /// Returns the approved asset for an upstream URL.
+    pub fn resolve(&self, url: &str) -> Result<Asset, Error> {
+        self.assets
+            .get(url)
+            .cloned()
+            .ok_or_else(|| Error::AssetNotInManifest(url.to_string()))
+    }
+}
+
+/// Sets the checksum an asset downloaded from its upstream URL is verified against, e.g. the one
+/// given with `--toolchain-checksum`.
+pub(crate) fn set_expected_checksum(url: &str, algo: ChecksumAlgo, checksum: &str) {
+    settings::current()
+        .expected_checksums
+        .lock()
+        .unwrap()
+        .insert(url.to_string(), (algo, checksum.to_lowercase()));
+}
+
+/// Validates a mirror URL, e.g. of `--mirror`.
+pub fn parse_mirror(mirror: &str) -> Result<String, Error> {
+    match reqwest::Url::parse(mirror) {
+        Ok(url) if ["http", "https"].contains(&url.scheme()) && url.host().is_some() => {
+            Ok(mirror.trim_end_matches('/').to_string())
+        }
+        _ => Err(Error::InvalidMirror(mirror.to_string())),
+    }
+}
+
+/// Returns the URL of the mirror of an upstream URL, which is the URL itself when its repository
+/// has no mirror.
+///
+/// Assets are downloaded from the mirror of their repository, as pairs of repository URL and
+/// mirror URL, under the same path, e.g. `<mirror>/<tag>/<file>`.
+fn mirror_url(mirrors: &[(String, String)], url: &str) -> String {
+    mirrors
+        .iter()
+        .find_map(|(repository, mirror)| {
+            let path = url.strip_prefix(repository.as_str())?;
+            path.starts_with('/').then(|| format!("{mirror}{path}"))
+        })
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// Resolves where an asset has to be downloaded from, which is the mirror of its repository, or
+/// the upstream URL itself, when no components manifest is set.
+pub(crate) fn resolve_asset(url: &str) -> Result<Asset, Error> {
+    let session = settings::current();
+    match session.components_manifest.lock().unwrap().as_ref() {
+        Some(manifest) => manifest.resolve(url),
+        None => Ok(Asset {
+            url: mirror_url(&session.settings.mirrors, url),
+            checksum: session.expected_checksums.lock().unwrap().get(url).cloned(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::{
+        components_manifest::{Asset, ComponentsManifest, mirror_url, parse_mirror},
+        manifest::ChecksumAlgo,
+    };
+
+    #[test]
+    fn test_components_manifest() {
+        let manifest = ComponentsManifest::parse(
+            r#"{
+                "xtensa_rust_version": "1.88.0.0",
+                "assets": {
+                    "https://github.com/rust.tar.xz": {
+                        "url": "https://mirror.example.com/rust.tar.xz",
+                        "sha256": "ABCD"
+                    },
+                    "https://github.com/llvm.tar.xz": {
+                        "url": "https://mirror.example.com/llvm.tar.xz"
+                    },
+                    "https://github.com/gcc.tar.xz": {
+                        "url": "https://mirror.example.com/gcc.tar.xz",
+                        "blake3": "EF01"
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(manifest.xtensa_rust_version.as_deref(), Some("1.88.0.0"));
+        assert_eq!(
+            manifest.resolve("https://github.com/rust.tar.xz").unwrap(),
+            Asset {
+                url: "https://mirror.example.com/rust.tar.xz".to_string(),
+                checksum: Some((ChecksumAlgo::Sha256, "abcd".to_string())),
+            }
+        );
+        assert_eq!(
+            manifest
+                .resolve("https://github.com/llvm.tar.xz")
+                .unwrap()
+                .checksum,
+            None
+        );
+        assert_eq!(
+            manifest
+                .resolve("https://github.com/gcc.tar.xz")
+                .unwrap()
+                .checksum,
+            Some((ChecksumAlgo::Blake3, "ef01".to_string()))
+        );
+        assert!(manifest.resolve("https://github.com/esp-idf.zip").is_err());
+
+        assert!(ComponentsManifest::parse("{}").is_err());
+        assert!(
+            ComponentsManifest::parse(r#"{"assets": {"https://github.com/rust.tar.xz": {}}}"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_mirrors() {
+        assert_eq!(
+            parse_mirror("https://mirror.example.com/llvm/").unwrap(),
+            "https://mirror.example.com/llvm"
+        );
+        assert!(parse_mirror("ftp://mirror.example.com").is_err());
+        assert!(parse_mirror("mirror.example.com").is_err());
+
+        let repository = "https://github.com/espressif/llvm-project/releases/download";
+        let mirrors = vec![(
+            repository.to_string(),
+            "https://mirror.example.com/llvm".to_string(),
+        )];
+        assert_eq!(
+            mirror_url(
+                &mirrors,
+                &format!("{repository}/esp-19.1.2_20250225/llvm.tar.xz")
+            ),
+            "https://mirror.example.com/llvm/esp-19.1.2_20250225/llvm.tar.xz"
+        );
+        // Only whole path segments of the repository match
+        let other = "https://github.com/espressif/llvm-project/releases/download-other/llvm.tar.xz";
+        assert_eq!(mirror_url(&mirrors, other), other);
+    }
+}
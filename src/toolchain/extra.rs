@@ -0,0 +1,265 @@
+//! Extra prebuilt artifacts, installed alongside the standard components.
+
+use crate::{
+    error::Error,
+    toolchain::{
+        Installable, download_file, extract_downloaded_file,
+        manifest::{checksum_file, installed_sources, installed_version},
+        progress::ProgressReporter,
+        rust::parse_checksum,
+    },
+};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use miette::Result;
+use std::{
+    fs::remove_file,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::fs::remove_dir_all;
+
+/// Directory of the toolchain directory the extra artifacts are extracted into.
+pub const EXTRA_ARTIFACTS_DIR: &str = "extra";
+/// Estimated size of an extra artifact, including its compressed download.
+const EXTRA_ARTIFACT_REQUIRED_SPACE: u64 = 500_000_000;
+
+#[derive(Debug, Clone)]
+pub struct ExtraArtifact {
+    /// Artifact name.
+    pub name: String,
+    /// URL of the artifact archive.
+    pub url: String,
+    /// Expected checksum of the archive, computed with the selected algorithm.
+    pub checksum: Option<String>,
+    /// Directory the artifact is extracted into.
+    pub path: PathBuf,
+}
+
+impl ExtraArtifact {
+    /// Create a new instance from a `name=url[:checksum]` specification.
+    pub fn new(spec: &str, toolchain_path: &Path) -> Result<Self, Error> {
+        let invalid = || Error::InvalidExtraArtifact(spec.to_string());
+        let (name, url) = spec.split_once('=').ok_or_else(invalid)?;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+            || name.starts_with('.')
+        {
+            return Err(invalid());
+        }
+        // URLs contain colons too, so only a trailing checksum is split off
+        let (url, checksum) = match url
+            .rsplit_once(':')
+            .and_then(|(url, checksum)| Some((url, parse_checksum(checksum).ok()?)))
+        {
+            Some((url, checksum)) => (url, Some(checksum)),
+            None => (url, None),
+        };
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            checksum,
+            path: toolchain_path.join(EXTRA_ARTIFACTS_DIR).join(name),
+        })
+    }
+
+    /// Creates the instance of an installed artifact from its component name in the manifest, e.g.
+    /// `Extra (openocd)`, or `None` if the component is not an extra artifact.
+    pub fn installed(component: &str, toolchain_path: &Path) -> Result<Option<Self>, Error> {
+        let Some(name) = component
+            .strip_prefix("Extra (")
+            .and_then(|name| name.strip_suffix(')'))
+        else {
+            return Ok(None);
+        };
+        let url = installed_sources(toolchain_path, component)?
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        Self::new(&format!("{name}={url}"), toolchain_path).map(Some)
+    }
+
+    /// Gets the name of the downloaded archive.
+    fn file_name(&self) -> String {
+        self.url
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            .split(['?', '#'])
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    /// Gets the binary path, the `bin` directory of the artifact if there is one.
+    pub fn get_bin_path(&self) -> PathBuf {
+        let bin_path = self.path.join("bin");
+        if bin_path.is_dir() {
+            return bin_path;
+        }
+        // Archives usually contain a single top-level directory
+        std::fs::read_dir(&self.path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path().join("bin"))
+            .find(|path| path.is_dir())
+            .unwrap_or_else(|| self.path.clone())
+    }
+}
+
+/// Validates an extra artifact specification, e.g. `openocd=https://example.com/openocd.tar.gz`.
+pub fn parse_extra_artifact(spec: &str) -> Result<String, Error> {
+    ExtraArtifact::new(spec, Path::new(""))?;
+    Ok(spec.to_string())
+}
+
+#[async_trait]
+impl Installable for ExtraArtifact {
+    async fn install(&self, reporter: Arc<dyn ProgressReporter>) -> Result<Vec<String>, Error> {
+        let toolchain_path = self.path.parent().and_then(Path::parent).unwrap();
+        if self.path.exists() {
+            let version = installed_version(toolchain_path, &self.name())
+                .ok()
+                .flatten();
+            if version == Some(self.version()) {
+                warn!(
+                    "Previous installation of '{}' exists in: '{}'. Reusing this installation",
+                    self.name,
+                    self.path.display()
+                );
+            } else {
+                remove_dir_all(&self.path)
+                    .await
+                    .map_err(|_| Error::RemoveDirectory(self.path.display().to_string()))?;
+            }
+        }
+
+        if !self.path.exists() {
+            info!("Installing extra artifact '{}'", self.name);
+            self.log_sources(&self.path);
+            let file_name = self.file_name();
+            let output_directory = self.path.display().to_string();
+            let archive = download_file(
+                self.url.clone(),
+                &file_name,
+                &output_directory,
+                false,
+                false,
+                None,
+                reporter.as_ref(),
+            )
+            .await?;
+            let archive = PathBuf::from(archive);
+            let checksum = checksum_file(&archive)?;
+            if let Some(expected) = &self.checksum
+                && expected != &checksum
+            {
+                remove_dir_all(&self.path).await?;
+                return Err(Error::ChecksumMismatch {
+                    file_name,
+                    expected: expected.clone(),
+                    actual: checksum,
+                });
+            }
+            if let Err(e) = extract_downloaded_file(&file_name, &archive, &output_directory, false)
+            {
+                remove_dir_all(&self.path).await?;
+                return Err(e);
+            }
+            remove_file(&archive)?;
+        }
+
+        debug!(
+            "'{}' binary path: {}",
+            self.name,
+            self.get_bin_path().display()
+        );
+        Ok(self.exports())
+    }
+
+    async fn uninstall(&self) -> Result<(), Error> {
+        if self.path.exists() {
+            info!("Uninstalling extra artifact '{}'", self.name);
+            remove_dir_all(&self.path)
+                .await
+                .map_err(|_| Error::RemoveDirectory(self.path.display().to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        format!("Extra ({})", self.name)
+    }
+
+    fn version(&self) -> String {
+        self.file_name()
+    }
+
+    fn required_space(&self) -> u64 {
+        EXTRA_ARTIFACT_REQUIRED_SPACE
+    }
+
+    fn sources(&self) -> Vec<String> {
+        vec![self.url.clone()]
+    }
+
+    fn exports(&self) -> Vec<String> {
+        let bin_path = self.get_bin_path();
+        #[cfg(windows)]
+        let export = format!("$Env:PATH = \"{};\" + $Env:PATH", bin_path.display());
+        #[cfg(unix)]
+        let export = format!("export PATH=\"{}:$PATH\"", bin_path.display());
+        vec![export]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::extra::{EXTRA_ARTIFACTS_DIR, ExtraArtifact, parse_extra_artifact};
+    use std::path::Path;
+
+    #[test]
+    fn test_extra_artifact() {
+        let checksum = "AB".repeat(32);
+        let artifact = ExtraArtifact::new(
+            &format!("openocd=https://example.com/openocd-v0.12.0.tar.gz:{checksum}"),
+            Path::new("/esp"),
+        )
+        .unwrap();
+        assert_eq!(artifact.name, "openocd");
+        assert_eq!(artifact.url, "https://example.com/openocd-v0.12.0.tar.gz");
+        assert_eq!(artifact.checksum, Some("ab".repeat(32)));
+        assert_eq!(
+            artifact.path,
+            Path::new("/esp").join(EXTRA_ARTIFACTS_DIR).join("openocd")
+        );
+        assert_eq!(artifact.file_name(), "openocd-v0.12.0.tar.gz");
+
+        let artifact =
+            ExtraArtifact::new("tool=https://example.com:8080/tool.zip", Path::new("/esp"))
+                .unwrap();
+        assert_eq!(artifact.url, "https://example.com:8080/tool.zip");
+        assert_eq!(artifact.checksum, None);
+
+        // SHA-1 checksums are shorter
+        let artifact = ExtraArtifact::new(
+            &format!("tool=https://example.com/tool.zip:{}", "ab".repeat(20)),
+            Path::new("/esp"),
+        )
+        .unwrap();
+        assert_eq!(artifact.url, "https://example.com/tool.zip");
+        assert_eq!(artifact.checksum, Some("ab".repeat(20)));
+
+        assert!(parse_extra_artifact("https://example.com/tool.zip").is_err());
+        assert!(parse_extra_artifact("=https://example.com/tool.zip").is_err());
+        assert!(parse_extra_artifact("../tool=https://example.com/tool.zip").is_err());
+        assert!(parse_extra_artifact("tool=example.com/tool.zip").is_err());
+    }
+}
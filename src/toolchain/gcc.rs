@@ -5,21 +5,62 @@ use crate::env::{get_windows_path_var, set_env_variable};
 use crate::{
     error::Error,
     host_triple::HostTriple,
-    toolchain::{Installable, download_file},
+    targets::Target,
+    toolchain::{
+        ComponentStatus, Installable, download_file, github_query, progress::ProgressReporter,
+        settings,
+    },
 };
 use async_trait::async_trait;
 use log::{debug, info, warn};
 use miette::Result;
-use std::path::{Path, PathBuf};
+use regex::Regex;
+#[cfg(unix)]
+use std::fs::read_dir;
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 #[cfg(windows)]
 use std::{env, fs::File};
+use strum::IntoEnumIterator;
 use tokio::fs::remove_dir_all;
 
-const DEFAULT_GCC_REPOSITORY: &str = "https://github.com/espressif/crosstool-NG/releases/download";
+pub(crate) const DEFAULT_GCC_REPOSITORY: &str =
+    "https://github.com/espressif/crosstool-NG/releases/download";
 const DEFAULT_GCC_RELEASE: &str = "15.2.0_20250920";
+/// GitHub API URL of the Crosstool-NG releases, by tag.
+const GCC_RELEASE_TAGS_API_URL: &str =
+    "https://api.github.com/repos/espressif/crosstool-NG/releases/tags";
+/// Estimated size of a GCC toolchain, including its compressed download.
+const GCC_REQUIRED_SPACE: u64 = 1_200_000_000;
+/// Crosstool-NG release version regex, e.g. `14.2.0_20241119`.
+const RE_GCC_RELEASE: &str = r"^\d+\.\d+\.\d+_\d{8}$";
 pub const RISCV_GCC: &str = "riscv32-esp-elf";
 pub const XTENSA_GCC: &str = "xtensa-esp-elf";
 
+/// Returns the GCC toolchain architecture a target is built with.
+pub fn gcc_arch(target: &Target) -> &'static str {
+    if target.is_xtensa() {
+        XTENSA_GCC
+    } else {
+        RISCV_GCC
+    }
+}
+
+/// Returns whether the GCC toolchain of an architecture is needed by any of the targets.
+///
+/// The RISC-V toolchain is shared by the RISC-V targets and the ULP coprocessors of the ESP32-S2
+/// and ESP32-S3, so only installations whose single target is the ESP32 don't need it.
+pub fn gcc_needed(arch: &str, targets: &HashSet<Target>) -> bool {
+    if arch == RISCV_GCC {
+        targets.iter().any(|target| target != &Target::ESP32)
+    } else {
+        targets.iter().any(|target| gcc_arch(target) == arch)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Gcc {
     /// Host triple.
@@ -42,6 +83,21 @@ impl Gcc {
         }
     }
 
+    /// Gets the URL of the release asset for the host.
+    pub fn get_dist_url(&self) -> Result<String, Error> {
+        let gcc_file = format!(
+            "{}-{}-{}.{}",
+            self.arch,
+            self.release_version,
+            get_arch(&self.host_triple, &self.arch)?,
+            get_artifact_extension(&self.host_triple)
+        );
+        Ok(format!(
+            "{}/esp-{}/{}",
+            DEFAULT_GCC_REPOSITORY, self.release_version, gcc_file
+        ))
+    }
+
     /// Create a new instance with default values and proper toolchain name.
     pub fn new(
         arch: &str,
@@ -65,11 +121,25 @@ impl Gcc {
             release_version,
         }
     }
+
+    /// Maps the 404 of the download, for releases that don't exist or have no asset for the
+    /// host, to an error naming the version.
+    fn not_found_error(&self, error: Error) -> Error {
+        match error {
+            Error::HttpError(status) if status == reqwest::StatusCode::NOT_FOUND.to_string() => {
+                Error::MissingGccAsset {
+                    version: self.release_version.clone(),
+                    host_triple: self.host_triple.to_string(),
+                }
+            }
+            error => error,
+        }
+    }
 }
 
 #[async_trait]
 impl Installable for Gcc {
-    async fn install(&self) -> Result<Vec<String>, Error> {
+    async fn install(&self, reporter: Arc<dyn ProgressReporter>) -> Result<Vec<String>, Error> {
         let extension = get_artifact_extension(&self.host_triple);
         info!("Installing GCC ({})", self.arch);
         debug!("GCC path: {}", self.path.display());
@@ -89,36 +159,24 @@ impl Installable for Gcc {
                 &self.path.display()
             );
         } else {
-            let gcc_file = format!(
-                "{}-{}-{}.{}",
-                self.arch,
-                self.release_version,
-                get_arch(&self.host_triple).unwrap(),
-                extension
-            );
-            let gcc_dist_url = format!(
-                "{}/esp-{}/{}",
-                DEFAULT_GCC_REPOSITORY, self.release_version, gcc_file
-            );
+            self.log_sources(&self.path);
             download_file(
-                gcc_dist_url,
+                self.get_dist_url()?,
                 &format!("{}.{}", &self.arch, extension),
                 &self.path.display().to_string(),
                 true,
                 false,
+                Some(&self.arch),
+                reporter.as_ref(),
             )
-            .await?;
+            .await
+            .map_err(|e| self.not_found_error(e))?;
         }
-        let mut exports: Vec<String> = Vec::new();
 
         #[cfg(windows)]
         if cfg!(windows) {
             File::create(self.path.join(&self.arch).join(&self.release_version))?;
 
-            exports.push(format!(
-                "$Env:PATH = \"{};\" + $Env:PATH",
-                &self.get_bin_path()
-            ));
             if self.arch == RISCV_GCC {
                 unsafe {
                     env::set_var("RISCV_GCC", self.get_bin_path());
@@ -129,19 +187,121 @@ impl Installable for Gcc {
                 }
             }
         }
+
+        Ok(self.exports())
+    }
+
+    async fn uninstall(&self) -> Result<(), Error> {
+        info!("Uninstalling GCC ({})", self.arch);
+        // The GCC path is '<toolchain>/<arch>/esp-<release>' on Unix and '<toolchain>' on Windows
         #[cfg(unix)]
-        exports.push(format!("export PATH=\"{}:$PATH\"", &self.get_bin_path()));
+        let toolchain_path = self.path.parent().and_then(Path::parent).unwrap();
+        #[cfg(windows)]
+        let toolchain_path = self.path.as_path();
+        let gcc_path = toolchain_path.join(&self.arch);
+        if gcc_path.exists() {
+            #[cfg(windows)]
+            if cfg!(windows) {
+                let mut updated_path = get_windows_path_var()?;
+                let gcc_version_path = format!(
+                    "{}\\esp-{}\\{}\\bin",
+                    gcc_path.display(),
+                    self.release_version,
+                    self.arch
+                );
+                updated_path = updated_path.replace(&format!("{gcc_version_path};"), "");
+                let bin_path = format!("{}\\bin", gcc_path.display());
+                updated_path = updated_path.replace(&format!("{bin_path};"), "");
 
-        Ok(exports)
+                set_env_variable("PATH", &updated_path)?;
+            }
+            remove_dir_all(&gcc_path)
+                .await
+                .map_err(|_| Error::RemoveDirectory(gcc_path.display().to_string()))?;
+        }
+        Ok(())
     }
 
     fn name(&self) -> String {
         format!("GCC ({})", self.arch)
     }
+
+    fn version(&self) -> String {
+        self.release_version.clone()
+    }
+
+    fn digest_files(&self) -> Vec<PathBuf> {
+        vec![Path::new(&self.get_bin_path()).join(format!(
+            "{}-gcc{}",
+            self.arch,
+            std::env::consts::EXE_SUFFIX
+        ))]
+    }
+
+    fn required_space(&self) -> u64 {
+        GCC_REQUIRED_SPACE
+    }
+
+    fn sources(&self) -> Vec<String> {
+        self.get_dist_url().into_iter().collect()
+    }
+
+    fn targets(&self) -> HashSet<Target> {
+        Target::iter()
+            .filter(|target| gcc_arch(target) == self.arch)
+            .collect()
+    }
+
+    fn exports(&self) -> Vec<String> {
+        #[cfg(windows)]
+        let export = format!("$Env:PATH = \"{};\" + $Env:PATH", &self.get_bin_path());
+        #[cfg(unix)]
+        let export = format!("export PATH=\"{}:$PATH\"", &self.get_bin_path());
+        vec![export]
+    }
+}
+
+/// Validates a Crosstool-NG release version, e.g. `14.2.0_20241119` or `esp-14.2.0_20241119`.
+///
+/// Only the format is checked, [`check_gcc_release`] checks that the release exists.
+pub fn parse_gcc_version(version: &str) -> Result<String, Error> {
+    let version = version.strip_prefix("esp-").unwrap_or(version);
+    let re_gcc_release = Regex::new(RE_GCC_RELEASE).unwrap();
+    if !re_gcc_release.is_match(version) {
+        return Err(Error::InvalidGccVersion(version.to_string()));
+    }
+    Ok(version.to_string())
+}
+
+/// Checks that the Crosstool-NG release of a version exists, before anything is installed.
+///
+/// When GitHub can't be queried, e.g. offline or rate limited, the check is skipped with a warning.
+pub async fn check_gcc_release(version: &str) -> Result<(), Error> {
+    let url = format!("{GCC_RELEASE_TAGS_API_URL}/esp-{version}");
+    let release = settings::spawn_blocking(move || github_query(&url))
+        .await
+        .map_err(|e| Error::GithubConnectivityError(format!("Failed to query GitHub API: {e}")))?;
+    release_check(version, release)
+}
+
+/// Maps the query of a Crosstool-NG release to the result of [`check_gcc_release`].
+fn release_check(version: &str, release: Result<serde_json::Value, Error>) -> Result<(), Error> {
+    match release {
+        Ok(_) => Ok(()),
+        Err(Error::HttpError(status))
+            if status.ends_with(&reqwest::StatusCode::NOT_FOUND.to_string()) =>
+        {
+            Err(Error::InvalidGccVersion(version.to_string()))
+        }
+        Err(e) => {
+            warn!("Could not check that the GCC release '{version}' exists: {e}");
+            Ok(())
+        }
+    }
 }
 
 /// Gets the name of the GCC arch based on the host triple.
-fn get_arch(host_triple: &HostTriple) -> Result<&str> {
+fn get_arch<'a>(host_triple: &HostTriple, gcc_arch: &str) -> Result<&'a str, Error> {
     match host_triple {
         HostTriple::X86_64AppleDarwin => Ok("x86_64-apple-darwin"),
         HostTriple::Aarch64AppleDarwin => Ok("aarch64-apple-darwin"),
@@ -150,6 +310,12 @@ fn get_arch(host_triple: &HostTriple) -> Result<&str> {
         HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => {
             Ok("x86_64-w64-mingw32")
         }
+        HostTriple::X86_64UnknownLinuxMusl | HostTriple::Aarch64UnknownLinuxMusl => {
+            Err(Error::NoAssetForHost {
+                component: format!("GCC ({gcc_arch})"),
+                host_triple: host_triple.to_string(),
+            })
+        }
     }
 }
 
@@ -161,42 +327,110 @@ fn get_artifact_extension(host_triple: &HostTriple) -> &str {
     }
 }
 
-/// Checks if the toolchain is pressent, if present uninstalls it.
-pub async fn uninstall_gcc_toolchains(
+/// Verifies the installed GCC toolchains.
+pub fn verify_gcc_toolchains(
     toolchain_path: &Path,
-    release_version: Option<String>,
-) -> Result<(), Error> {
-    info!("Uninstalling GCC");
+) -> Result<Vec<(String, ComponentStatus)>, Error> {
+    let mut components = Vec::new();
+    for arch in [XTENSA_GCC, RISCV_GCC] {
+        let gcc_path = toolchain_path.join(arch);
+        if !gcc_path.exists() {
+            continue;
+        }
+        let gcc_binary = format!("{arch}-gcc{}", std::env::consts::EXE_SUFFIX);
 
-    #[cfg_attr(not(windows), allow(unused_variables))]
-    // release_version is only used in the windows block, but is also being passed, and so clippy will complain if not marked unused across platforms
-    let release_version = release_version.unwrap_or_else(|| DEFAULT_GCC_RELEASE.to_string());
+        #[cfg(unix)]
+        let binaries = read_dir(&gcc_path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().join(arch).join("bin").join(&gcc_binary))
+            .collect::<Vec<PathBuf>>();
+        #[cfg(windows)]
+        let binaries = vec![gcc_path.join("bin").join(&gcc_binary)];
 
-    let gcc_toolchains = vec![XTENSA_GCC, RISCV_GCC];
+        components.push((format!("GCC ({arch})"), ComponentStatus::check(&binaries)));
+    }
 
-    for toolchain in gcc_toolchains {
-        let gcc_path = toolchain_path.join(toolchain);
-        if gcc_path.exists() {
-            #[cfg(windows)]
-            if cfg!(windows) {
-                let mut updated_path = get_windows_path_var()?;
-                let gcc_version_path = format!(
-                    "{}\\esp-{}\\{}\\bin",
-                    gcc_path.display(),
-                    release_version,
-                    toolchain
-                );
-                updated_path = updated_path.replace(&format!("{gcc_version_path};"), "");
-                let bin_path = format!("{}\\bin", gcc_path.display());
-                updated_path = updated_path.replace(&format!("{bin_path};"), "");
+    Ok(components)
+}
 
-                set_env_variable("PATH", &updated_path)?;
-            }
-            remove_dir_all(&gcc_path)
-                .await
-                .map_err(|_| Error::RemoveDirectory(gcc_path.display().to_string()))?;
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::Error,
+        host_triple::HostTriple,
+        targets::Target,
+        toolchain::gcc::{Gcc, RISCV_GCC, XTENSA_GCC, gcc_arch, gcc_needed, release_check},
+    };
+    use std::{collections::HashSet, path::Path};
+
+    #[test]
+    fn test_gcc_arch() {
+        assert_eq!(gcc_arch(&Target::ESP32S3), XTENSA_GCC);
+        assert_eq!(gcc_arch(&Target::ESP32C6), RISCV_GCC);
+        assert_eq!(gcc_arch(&Target::ESP32P4), RISCV_GCC);
+    }
+
+    #[test]
+    fn test_gcc_needed() {
+        let esp32 = HashSet::from([Target::ESP32]);
+        assert!(gcc_needed(XTENSA_GCC, &esp32));
+        assert!(!gcc_needed(RISCV_GCC, &esp32));
+        // The ULP coprocessor of the ESP32-S3 uses the RISC-V toolchain
+        let esp32s3 = HashSet::from([Target::ESP32S3]);
+        assert!(gcc_needed(RISCV_GCC, &esp32s3));
+        let esp32c3 = HashSet::from([Target::ESP32C3]);
+        assert!(!gcc_needed(XTENSA_GCC, &esp32c3));
+        assert!(gcc_needed(RISCV_GCC, &esp32c3));
+    }
+
+    #[test]
+    fn test_gcc_dist_url() {
+        let gcc = Gcc::new(
+            XTENSA_GCC,
+            &HostTriple::Aarch64UnknownLinuxGnu,
+            Path::new("/tmp"),
+            Some("14.2.0_20241119".to_string()),
+        );
+        assert_eq!(
+            gcc.get_dist_url().unwrap(),
+            "https://github.com/espressif/crosstool-NG/releases/download/esp-14.2.0_20241119/xtensa-esp-elf-14.2.0_20241119-aarch64-linux-gnu.tar.xz"
+        );
+
+        for host_triple in [
+            HostTriple::X86_64UnknownLinuxMusl,
+            HostTriple::Aarch64UnknownLinuxMusl,
+        ] {
+            let gcc = Gcc::new(XTENSA_GCC, &host_triple, Path::new("/tmp"), None);
+            assert!(gcc.get_dist_url().is_err());
         }
     }
 
-    Ok(())
+    #[test]
+    fn test_gcc_not_found_error() {
+        let gcc = Gcc::new(
+            XTENSA_GCC,
+            &HostTriple::X86_64UnknownLinuxGnu,
+            Path::new("/tmp"),
+            Some("14.9.0_20991231".to_string()),
+        );
+        let error = gcc.not_found_error(Error::HttpError("404 Not Found".to_string()));
+        assert!(error.to_string().contains("'14.9.0_20991231'"));
+        assert!(matches!(error, Error::MissingGccAsset { .. }));
+        assert!(matches!(
+            gcc.not_found_error(Error::HttpError("500 Internal Server Error".to_string())),
+            Error::HttpError(_)
+        ));
+    }
+
+    #[test]
+    fn test_release_check() {
+        assert!(release_check("14.2.0_20241119", Ok(serde_json::json!({}))).is_ok());
+        let not_found = Error::HttpError("GitHub API returned status code: 404 Not Found".into());
+        assert!(matches!(
+            release_check("14.9.0_20991231", Err(not_found)),
+            Err(Error::InvalidGccVersion(version)) if version == "14.9.0_20991231"
+        ));
+        // Failing to query GitHub doesn't prevent the installation
+        assert!(release_check("14.2.0_20241119", Err(Error::GithubRateLimit)).is_ok());
+    }
 }
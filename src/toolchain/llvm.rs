@@ -1,26 +1,36 @@
 //! LLVM Toolchain source and installation tools.
 
+#[cfg(unix)]
+use crate::env::get_espup_dir;
 #[cfg(windows)]
 use crate::env::{delete_env_variable, get_windows_path_var, set_env_variable};
 use crate::{
     error::Error,
     host_triple::HostTriple,
-    toolchain::{Installable, download_file, rust::RE_EXTENDED_SEMANTIC_VERSION},
+    toolchain::{
+        ComponentStatus, Installable, download_file, is_cross_host, progress::ProgressReporter,
+        rust::RE_EXTENDED_SEMANTIC_VERSION,
+    },
 };
 use async_trait::async_trait;
-#[cfg(unix)]
-use directories::BaseDirs;
 use log::{info, warn};
 use miette::Result;
 use regex::Regex;
-use std::path::{Path, PathBuf};
 #[cfg(windows)]
-use std::{env, fs::File};
+use std::fs::File;
+use std::{
+    env,
+    ffi::OsStr,
+    fs::read_dir,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 #[cfg(unix)]
 use std::{fs::create_dir_all, os::unix::fs::symlink};
 use tokio::fs::remove_dir_all;
 
-const DEFAULT_LLVM_REPOSITORY: &str = "https://github.com/espressif/llvm-project/releases/download";
+pub(crate) const DEFAULT_LLVM_REPOSITORY: &str =
+    "https://github.com/espressif/llvm-project/releases/download";
 const DEFAULT_LLVM_15_VERSION: &str = "esp-15.0.0-20221201";
 #[cfg(windows)]
 const OLD_LLVM_16_VERSION: &str = "esp-16.0.0-20230516";
@@ -30,8 +40,29 @@ const DEFAULT_LLVM_18_VERSION: &str = "esp-18.1.2_20240912";
 const DEFAULT_LLVM_19_VERSION: &str = "esp-19.1.2_20250225";
 const DEFAULT_LLVM_20_VERSION: &str = "esp-20.1.1_20250829";
 const DEFAULT_LLVM_21_VERSION: &str = "esp-21.1.3_20260408";
+/// LLVM releases that can be pinned with `--llvm-version`.
+const KNOWN_LLVM_VERSIONS: [&str; 7] = [
+    DEFAULT_LLVM_15_VERSION,
+    DEFAULT_LLVM_16_VERSION,
+    DEFAULT_LLVM_17_VERSION,
+    DEFAULT_LLVM_18_VERSION,
+    DEFAULT_LLVM_19_VERSION,
+    DEFAULT_LLVM_20_VERSION,
+    DEFAULT_LLVM_21_VERSION,
+];
+
+/// Estimated size of the LLVM libraries, including their compressed download.
+const LLVM_LIBS_REQUIRED_SPACE: u64 = 600_000_000;
+/// Estimated size of the whole LLVM, including its compressed download.
+const LLVM_FULL_REQUIRED_SPACE: u64 = 2_000_000_000;
 
 pub const CLANG_NAME: &str = "xtensa-esp32-elf-clang";
+#[cfg(all(unix, not(target_os = "macos")))]
+const LIBCLANG_NAME: &str = "libclang.so";
+#[cfg(target_os = "macos")]
+const LIBCLANG_NAME: &str = "libclang.dylib";
+#[cfg(windows)]
+const LIBCLANG_NAME: &str = "libclang.dll";
 
 #[derive(Debug, Clone, Default)]
 pub struct Llvm {
@@ -53,7 +84,14 @@ pub struct Llvm {
 
 impl Llvm {
     /// Gets the name of the LLVM arch based on the host triple.
-    fn get_arch(host_triple: &HostTriple, version: &str) -> String {
+    fn get_arch(host_triple: &HostTriple, version: &str) -> Result<String, Error> {
+        if host_triple.is_musl() {
+            return Err(Error::NoAssetForHost {
+                component: "LLVM".to_string(),
+                host_triple: host_triple.to_string(),
+            });
+        }
+
         if version == DEFAULT_LLVM_17_VERSION
             || version == DEFAULT_LLVM_18_VERSION
             || version == DEFAULT_LLVM_19_VERSION
@@ -68,8 +106,11 @@ impl Llvm {
                 HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => {
                     "x86_64-w64-mingw32"
                 }
+                HostTriple::X86_64UnknownLinuxMusl | HostTriple::Aarch64UnknownLinuxMusl => {
+                    unreachable!()
+                }
             };
-            arch.to_string()
+            Ok(arch.to_string())
         } else {
             let arch = match host_triple {
                 HostTriple::Aarch64AppleDarwin => "macos-arm64",
@@ -77,8 +118,11 @@ impl Llvm {
                 HostTriple::X86_64UnknownLinuxGnu => "linux-amd64",
                 HostTriple::Aarch64UnknownLinuxGnu => "linux-arm64",
                 HostTriple::X86_64PcWindowsMsvc | HostTriple::X86_64PcWindowsGnu => "win64",
+                HostTriple::X86_64UnknownLinuxMusl | HostTriple::Aarch64UnknownLinuxMusl => {
+                    unreachable!()
+                }
             };
-            arch.to_string()
+            Ok(arch.to_string())
         }
     }
 
@@ -99,12 +143,44 @@ impl Llvm {
         }
     }
 
+    /// Creates an instance for an existing installation of the LLVM `version`, which is extended
+    /// when clang was installed along with libclang.
+    pub fn installed(
+        toolchain_path: &Path,
+        host_triple: &HostTriple,
+        xtensa_rust_version: &str,
+        version: &str,
+    ) -> Result<Self, Error> {
+        let llvm = Self::new(
+            toolchain_path,
+            host_triple,
+            false,
+            xtensa_rust_version,
+            Some(version),
+        )?;
+        if Path::new(&llvm.get_bin_path()).exists() {
+            Self::new(
+                toolchain_path,
+                host_triple,
+                true,
+                xtensa_rust_version,
+                Some(version),
+            )
+        } else {
+            Ok(llvm)
+        }
+    }
+
     /// Create a new instance with default values and proper toolchain version.
+    ///
+    /// The LLVM release is chosen based on the Xtensa Rust version, unless one is pinned with
+    /// `llvm_version`.
     pub fn new(
         toolchain_path: &Path,
         host_triple: &HostTriple,
         extended: bool,
         xtensa_rust_version: &str,
+        llvm_version: Option<&str>,
     ) -> Result<Self, Error> {
         let re_extended: Regex = Regex::new(RE_EXTENDED_SEMANTIC_VERSION).unwrap();
         let (major, minor, patch, subpatch) = match re_extended.captures(xtensa_rust_version) {
@@ -145,6 +221,20 @@ impl Llvm {
         } else {
             DEFAULT_LLVM_21_VERSION.to_string()
         };
+        let version = match llvm_version {
+            Some(llvm_version) => {
+                let llvm_version = parse_llvm_version(llvm_version)?;
+                if release_major(&llvm_version) != release_major(&version) {
+                    warn!(
+                        "Xtensa Rust {xtensa_rust_version} is built with LLVM {}, using LLVM {} instead may break bindgen",
+                        release_major(&version),
+                        release_major(&llvm_version)
+                    );
+                }
+                llvm_version
+            }
+            None => version,
+        };
 
         let name = if version == DEFAULT_LLVM_17_VERSION
             || version == DEFAULT_LLVM_18_VERSION
@@ -162,7 +252,7 @@ impl Llvm {
                 "{}{}-{}.tar.xz",
                 name,
                 version,
-                Self::get_arch(host_triple, &version)
+                Self::get_arch(host_triple, &version)?
             );
 
             let file_name_libs = if version != DEFAULT_LLVM_17_VERSION
@@ -212,6 +302,69 @@ impl Llvm {
         })
     }
 
+    /// Verifies the LLVM toolchain, if installed.
+    ///
+    /// LLVM is only required to provide `libclang`, so it checks that it is present.
+    pub fn verify(toolchain_path: &Path) -> Result<Option<ComponentStatus>, Error> {
+        let llvm_path = toolchain_path.join(CLANG_NAME);
+        if !llvm_path.exists() {
+            return Ok(None);
+        }
+
+        #[cfg(unix)]
+        let lib_paths = read_dir(&llvm_path)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path().join("esp-clang").join("lib"))
+            .collect::<Vec<PathBuf>>();
+        #[cfg(windows)]
+        let lib_paths = vec![llvm_path.join("esp-clang").join("bin")];
+
+        let mut missing = Vec::new();
+        for lib_path in lib_paths {
+            let has_libclang = read_dir(&lib_path).is_ok_and(|mut entries| {
+                entries.any(|entry| {
+                    entry.is_ok_and(|entry| {
+                        entry.file_name().to_string_lossy().starts_with("libclang")
+                    })
+                })
+            });
+            if !has_libclang {
+                missing.push(lib_path.join(LIBCLANG_NAME));
+            }
+        }
+
+        if missing.is_empty() {
+            Ok(Some(ComponentStatus::Ok))
+        } else {
+            Ok(Some(ComponentStatus::Corrupt(missing)))
+        }
+    }
+
+    /// Warns when a `clang` or libclang found earlier on `PATH` could be picked up by bindgen
+    /// instead of the installed one, and whether the export file fixes it.
+    fn warn_shadowing_clang(&self, path_var: &OsStr) {
+        let Some(found) = find_shadowing_clang(path_var, &self.path) else {
+            return;
+        };
+        warn!(
+            "'{}' is on PATH and may shadow the Xtensa LLVM, which causes bindgen failures in esp-idf-sys builds",
+            found.display()
+        );
+        let variable = if found.file_name() == Some(OsStr::new(LIBCLANG_NAME)) {
+            "LIBCLANG_PATH"
+        } else if self.extended {
+            "CLANG_PATH"
+        } else {
+            warn!(
+                "The export file only sets 'LIBCLANG_PATH', which doesn't fix it: remove it from PATH, or use '--extended-llvm' to also set 'CLANG_PATH'"
+            );
+            return;
+        };
+        warn!(
+            "Source the export file before building, the '{variable}' it sets takes precedence over PATH"
+        );
+    }
+
     /// Uninstall LLVM toolchain.
     pub async fn uninstall(toolchain_path: &Path) -> Result<(), Error> {
         info!("Uninstalling Xtensa LLVM");
@@ -299,7 +452,7 @@ impl Llvm {
                 .map_err(|_| Error::RemoveDirectory(llvm_path.display().to_string()))?;
             #[cfg(unix)]
             if cfg!(unix) {
-                let espup_dir = BaseDirs::new().unwrap().home_dir().join(".espup");
+                let espup_dir = get_espup_dir()?;
 
                 if espup_dir.exists() {
                     remove_dir_all(espup_dir.display().to_string())
@@ -312,11 +465,61 @@ impl Llvm {
     }
 }
 
+/// Finds the first `clang` executable or libclang library on `PATH` outside of `llvm_path`.
+fn find_shadowing_clang(path_var: &OsStr, llvm_path: &Path) -> Option<PathBuf> {
+    env::split_paths(path_var)
+        .filter(|dir| !dir.starts_with(llvm_path))
+        .flat_map(|dir| {
+            [
+                dir.join(format!("clang{}", env::consts::EXE_SUFFIX)),
+                dir.join(LIBCLANG_NAME),
+            ]
+        })
+        .find(|file| file.is_file())
+}
+
+/// Returns the major version of an LLVM release, e.g. `19` for `esp-19.1.2_20250225`.
+fn release_major(release: &str) -> &str {
+    release_version(release)
+        .split('.')
+        .next()
+        .unwrap_or_default()
+}
+
+/// Returns the version of an LLVM release, e.g. `19.1.2` for `esp-19.1.2_20250225`.
+fn release_version(release: &str) -> &str {
+    release
+        .trim_start_matches("esp-")
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+}
+
+/// Resolves an LLVM version, either a major version (`19`), a full version (`19.1.2`) or a
+/// release (`esp-19.1.2_20250225`), into the release it is published as.
+pub fn parse_llvm_version(version: &str) -> Result<String, Error> {
+    KNOWN_LLVM_VERSIONS
+        .iter()
+        .find(|release| {
+            let release_version = release_version(release);
+            **release == version
+                || release_version == version
+                || release_version.starts_with(&format!("{version}."))
+        })
+        .map(|release| release.to_string())
+        .ok_or_else(|| Error::UnsupportedLlvmVersion {
+            version: version.to_string(),
+            supported: KNOWN_LLVM_VERSIONS
+                .iter()
+                .map(|release| release_version(release))
+                .collect::<Vec<_>>()
+                .join(", "),
+        })
+}
+
 #[async_trait]
 impl Installable for Llvm {
-    async fn install(&self) -> Result<Vec<String>, Error> {
-        let mut exports: Vec<String> = Vec::new();
-
+    async fn install(&self, reporter: Arc<dyn ProgressReporter>) -> Result<Vec<String>, Error> {
         #[cfg(unix)]
         let install_path = if self.extended {
             Path::new(&self.path).join("esp-clang").join("include")
@@ -337,6 +540,7 @@ impl Installable for Llvm {
             );
         } else {
             info!("Installing Xtensa LLVM");
+            self.log_sources(&self.path);
             if let Some(file_name_libs) = &self.file_name_libs {
                 download_file(
                     format!("{}/{}", self.repository_url, file_name_libs),
@@ -344,6 +548,8 @@ impl Installable for Llvm {
                     self.path.to_str().unwrap(),
                     true,
                     false,
+                    Some("esp-clang"),
+                    reporter.as_ref(),
                 )
                 .await?;
             }
@@ -354,6 +560,8 @@ impl Installable for Llvm {
                     self.path.to_str().unwrap(),
                     true,
                     false,
+                    Some("esp-clang"),
+                    reporter.as_ref(),
                 )
                 .await?;
             }
@@ -363,20 +571,18 @@ impl Installable for Llvm {
         if cfg!(windows) {
             File::create(self.path.join(&self.version))?;
             let libclang_dll = format!("{}\\libclang.dll", self.get_lib_path());
-            exports.push(format!("$Env:LIBCLANG_PATH = \"{libclang_dll}\""));
-            exports.push(format!(
-                "$Env:PATH = \"{};\" + $Env:PATH",
-                self.get_lib_path()
-            ));
             unsafe {
                 env::set_var("LIBCLANG_BIN_PATH", self.get_lib_path());
                 env::set_var("LIBCLANG_PATH", libclang_dll);
+                if self.extended {
+                    env::set_var("CLANG_PATH", self.get_bin_path());
+                }
             }
         }
+        // The libraries of other hosts can't be loaded, so they are not linked into the espup directory
         #[cfg(unix)]
-        if cfg!(unix) {
-            exports.push(format!("export LIBCLANG_PATH=\"{}\"", self.get_lib_path()));
-            let espup_dir = BaseDirs::new().unwrap().home_dir().join(".espup");
+        if cfg!(unix) && !is_cross_host() {
+            let espup_dir = get_espup_dir()?;
 
             if !espup_dir.exists() {
                 create_dir_all(espup_dir.display().to_string())
@@ -396,22 +602,163 @@ impl Installable for Llvm {
             symlink(self.get_lib_path(), llvm_symlink_path)?;
         }
 
+        if !is_cross_host()
+            && let Some(path_var) = env::var_os("PATH")
+        {
+            self.warn_shadowing_clang(&path_var);
+        }
+
+        Ok(self.exports())
+    }
+
+    async fn uninstall(&self) -> Result<(), Error> {
+        let toolchain_path = self
+            .path
+            .ancestors()
+            .find(|path| path.ends_with(CLANG_NAME))
+            .and_then(Path::parent)
+            .unwrap();
+        Llvm::uninstall(toolchain_path).await
+    }
+
+    fn name(&self) -> String {
+        "LLVM".to_string()
+    }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn digest_files(&self) -> Vec<PathBuf> {
+        vec![Path::new(&self.get_lib_path()).join(LIBCLANG_NAME)]
+    }
+
+    fn required_space(&self) -> u64 {
+        let libs = self
+            .file_name_libs
+            .as_ref()
+            .map_or(0, |_| LLVM_LIBS_REQUIRED_SPACE);
+        let full = self
+            .file_name_full
+            .as_ref()
+            .map_or(0, |_| LLVM_FULL_REQUIRED_SPACE);
+        libs + full
+    }
+
+    fn sources(&self) -> Vec<String> {
+        [&self.file_name_libs, &self.file_name_full]
+            .into_iter()
+            .flatten()
+            .map(|file_name| format!("{}/{}", self.repository_url, file_name))
+            .collect()
+    }
+
+    fn exports(&self) -> Vec<String> {
+        let mut exports: Vec<String> = Vec::new();
+        #[cfg(windows)]
+        if cfg!(windows) {
+            let libclang_dll = format!("{}\\libclang.dll", self.get_lib_path());
+            exports.push(format!("$Env:LIBCLANG_PATH = \"{libclang_dll}\""));
+            exports.push(format!(
+                "$Env:PATH = \"{};\" + $Env:PATH",
+                self.get_lib_path()
+            ));
+        }
+        #[cfg(unix)]
+        if cfg!(unix) {
+            exports.push(format!("export LIBCLANG_PATH=\"{}\"", self.get_lib_path()));
+        }
+
         if self.extended {
             #[cfg(windows)]
-            if cfg!(windows) {
-                exports.push(format!("$Env:CLANG_PATH = \"{}\"", self.get_bin_path()));
-                unsafe {
-                    env::set_var("CLANG_PATH", self.get_bin_path());
-                }
-            }
+            exports.push(format!("$Env:CLANG_PATH = \"{}\"", self.get_bin_path()));
             #[cfg(unix)]
             exports.push(format!("export CLANG_PATH=\"{}\"", self.get_bin_path()));
         }
+        exports
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        host_triple::HostTriple,
+        toolchain::llvm::{
+            DEFAULT_LLVM_19_VERSION, LIBCLANG_NAME, Llvm, find_shadowing_clang, parse_llvm_version,
+        },
+    };
+    use std::{env, fs::write, path::Path};
+    use tempfile::TempDir;
 
-        Ok(exports)
+    #[test]
+    fn test_find_shadowing_clang() {
+        let temp_dir = TempDir::new().unwrap();
+        let llvm_path = temp_dir.path().join("esp-clang");
+        let llvm_bin = llvm_path.join("bin");
+        let system_bin = temp_dir.path().join("usr").join("bin");
+        let system_lib = temp_dir.path().join("usr").join("lib");
+        for dir in [&llvm_bin, &system_bin, &system_lib] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let clang_name = format!("clang{}", env::consts::EXE_SUFFIX);
+        write(llvm_bin.join(&clang_name), "").unwrap();
+
+        let path_var = env::join_paths([&llvm_bin, &system_bin, &system_lib]).unwrap();
+        assert_eq!(find_shadowing_clang(&path_var, &llvm_path), None);
+
+        write(system_lib.join(LIBCLANG_NAME), "").unwrap();
+        assert_eq!(
+            find_shadowing_clang(&path_var, &llvm_path),
+            Some(system_lib.join(LIBCLANG_NAME))
+        );
+        write(system_bin.join(&clang_name), "").unwrap();
+        assert_eq!(
+            find_shadowing_clang(&path_var, &llvm_path),
+            Some(system_bin.join(clang_name))
+        );
     }
 
-    fn name(&self) -> String {
-        "LLVM".to_string()
+    #[test]
+    fn test_parse_llvm_version() {
+        assert_eq!(parse_llvm_version("19").unwrap(), DEFAULT_LLVM_19_VERSION);
+        assert_eq!(parse_llvm_version("19.1").unwrap(), DEFAULT_LLVM_19_VERSION);
+        assert_eq!(
+            parse_llvm_version("19.1.2").unwrap(),
+            DEFAULT_LLVM_19_VERSION
+        );
+        assert_eq!(
+            parse_llvm_version(DEFAULT_LLVM_19_VERSION).unwrap(),
+            DEFAULT_LLVM_19_VERSION
+        );
+        assert!(parse_llvm_version("1").is_err());
+        assert!(parse_llvm_version("19.1.3").is_err());
+        assert!(parse_llvm_version("14").is_err());
+
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let llvm = Llvm::new(Path::new("/esp"), &host_triple, false, "1.88.0.0", None).unwrap();
+        assert_eq!(llvm.version, DEFAULT_LLVM_19_VERSION);
+        let llvm = Llvm::new(
+            Path::new("/esp"),
+            &host_triple,
+            false,
+            "1.88.0.0",
+            Some("15.0.0"),
+        )
+        .unwrap();
+        assert_eq!(llvm.version, "esp-15.0.0-20221201");
+        assert_eq!(
+            llvm.file_name_libs.as_deref(),
+            Some("libs_llvm-esp-15.0.0-20221201-linux-amd64.tar.xz")
+        );
+        assert!(
+            Llvm::new(
+                Path::new("/esp"),
+                &HostTriple::X86_64UnknownLinuxMusl,
+                false,
+                "1.88.0.0",
+                Some("19")
+            )
+            .is_err()
+        );
     }
 }
@@ -0,0 +1,849 @@
+//! Installation manifest, describing exactly which toolchains were installed.
+
+use crate::{
+    error::Error,
+    host_triple::HostTriple,
+    targets::Target,
+    toolchain::{InstalledComponent, settings},
+};
+use clap::ValueEnum;
+use log::debug;
+use serde_json::json;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fs::{File, create_dir_all, read, read_dir, remove_file},
+    io::{self, Write, copy},
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use strum::{Display, EnumIter, EnumString, IntoEnumIterator};
+use tempfile::NamedTempFile;
+
+/// Name of the manifest file, created inside the toolchain directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Name of the file marking a toolchain directory espup is installing into, removed once the
+/// manifest is written.
+const INSTALLING_MARKER_FILE: &str = ".espup-installing";
+
+/// Algorithms checksums can be computed with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Display, EnumIter, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ChecksumAlgo {
+    #[default]
+    Sha256,
+    Sha1,
+    Blake3,
+}
+
+impl ChecksumAlgo {
+    /// Computes the checksum of a file.
+    pub fn checksum_file(self, path: &Path) -> Result<String, Error> {
+        let mut file = File::open(path)?;
+        let checksum = match self {
+            ChecksumAlgo::Sha256 => {
+                let mut hasher = Sha256::new();
+                copy(&mut file, &mut hasher)?;
+                format!("{:x}", hasher.finalize())
+            }
+            ChecksumAlgo::Sha1 => {
+                let mut hasher = Sha1::new();
+                copy(&mut file, &mut hasher)?;
+                format!("{:x}", hasher.finalize())
+            }
+            ChecksumAlgo::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                copy(&mut file, &mut hasher)?;
+                hasher.finalize().to_hex().to_string()
+            }
+        };
+        Ok(checksum)
+    }
+
+    /// Returns the number of hexadecimal characters of its checksums.
+    pub fn hex_len(self) -> usize {
+        match self {
+            ChecksumAlgo::Sha256 | ChecksumAlgo::Blake3 => 64,
+            ChecksumAlgo::Sha1 => 40,
+        }
+    }
+}
+
+/// Returns the algorithm the checksums of the downloaded and installed files are computed with.
+pub(crate) fn checksum_algo() -> ChecksumAlgo {
+    settings::current().settings.checksum_algo
+}
+
+/// Computes the checksum of a file with the selected algorithm.
+pub fn checksum_file(path: &Path) -> Result<String, Error> {
+    checksum_algo().checksum_file(path)
+}
+
+/// Records the checksum of a downloaded file.
+pub(crate) fn record_checksum(url: &str, checksum: String) {
+    settings::current()
+        .checksums
+        .lock()
+        .unwrap()
+        .insert(url.to_string(), checksum);
+}
+
+/// Writes the manifest with the version, sources and checksums of every installed component.
+///
+/// Checksums are only known for the files downloaded during this run, components that reused a
+/// previous installation have a `null` checksum. They are recorded under the name of the
+/// algorithm they were computed with, e.g. `"sha256"`. Installations for a non-native host,
+/// which were never executed, are labeled with `"native": false`.
+///
+/// The `options` the installation was made with are recorded, so it can be repaired. The shell
+/// profiles recorded by [`record_profile`] are kept, as they still source the export file.
+///
+/// The time of the installation, and the version of espup that made it, are kept when the Xtensa
+/// Rust version didn't change, so reinstalling or updating to the same version doesn't reset the
+/// age of the installation. The version of espup that last wrote the manifest is recorded too.
+pub fn write_manifest(
+    toolchain_dir: &Path,
+    host_triple: &HostTriple,
+    native: bool,
+    targets: &HashSet<Target>,
+    components: &[InstalledComponent],
+    options: &serde_json::Value,
+) -> Result<PathBuf, Error> {
+    let xtensa_rust_version = components
+        .iter()
+        .find(|component| component.name == "Xtensa Rust")
+        .map(|component| component.version.clone());
+    let previous = match installed_version(toolchain_dir, "Xtensa Rust") {
+        Ok(version) if version == xtensa_rust_version => Some(read_manifest(toolchain_dir)?),
+        _ => None,
+    };
+    let installed_at = previous
+        .as_ref()
+        .and_then(|manifest| manifest["installed_at"].as_u64())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs()
+        });
+    let profiles = read_manifest(toolchain_dir)
+        .map(|manifest| recorded_profiles(&manifest))
+        .unwrap_or_default();
+    let installed_with_version = previous
+        .as_ref()
+        .and_then(|manifest| manifest["installed_with_version"].as_str())
+        .unwrap_or(env!("CARGO_PKG_VERSION"))
+        .to_string();
+    let session = settings::current();
+    let checksums = session.checksums.lock().unwrap();
+    let algo = session.settings.checksum_algo;
+    let algo_name = algo.to_string();
+    let mut components: Vec<&InstalledComponent> = components.iter().collect();
+    components.sort_by(|a, b| a.name.cmp(&b.name));
+    let components: Vec<serde_json::Value> = components
+        .iter()
+        .map(|component| {
+            let sources: Vec<serde_json::Value> = component
+                .sources
+                .iter()
+                .map(|url| json!({ "url": url, algo_name.as_str(): checksums.get(url) }))
+                .collect();
+            let files: Vec<serde_json::Value> = component
+                .files
+                .iter()
+                .filter_map(|path| {
+                    let relative_path = path.strip_prefix(toolchain_dir).ok()?;
+                    let checksum = algo.checksum_file(path).ok()?;
+                    Some(json!({ "path": relative_path, algo_name.as_str(): checksum }))
+                })
+                .collect();
+            json!({
+                "name": component.name,
+                "version": component.version,
+                "sources": sources,
+                "files": files,
+            })
+        })
+        .collect();
+    let mut targets: Vec<String> = targets.iter().map(ToString::to_string).collect();
+    targets.sort();
+    let manifest = json!({
+        "host_triple": host_triple.to_string(),
+        "native": native,
+        "targets": targets,
+        "installed_at": installed_at,
+        "installed_with_version": installed_with_version,
+        "updated_with_version": env!("CARGO_PKG_VERSION"),
+        "options": options,
+        "profiles": profiles,
+        "components": components,
+    });
+
+    let manifest_path = toolchain_dir.join(MANIFEST_FILE);
+    debug!("Writing manifest to '{}'", manifest_path.display());
+    let contents = serde_json::to_string_pretty(&manifest).map_err(|_| Error::SerializeJson)?;
+    write_atomically(&manifest_path, |file| file.write_all(contents.as_bytes()))?;
+    let marker_path = toolchain_dir.join(INSTALLING_MARKER_FILE);
+    if marker_path.exists() {
+        remove_file(&marker_path)?;
+    }
+    Ok(manifest_path)
+}
+
+/// Marks `toolchain_dir` as being installed into by espup, until its manifest is written.
+pub fn mark_installing(toolchain_dir: &Path) -> Result<(), Error> {
+    create_dir_all(toolchain_dir)
+        .map_err(|_| Error::CreateDirectory(toolchain_dir.display().to_string()))?;
+    File::create(toolchain_dir.join(INSTALLING_MARKER_FILE))?;
+    Ok(())
+}
+
+/// Writes a file through a temporary file in the same directory, which is then renamed over it,
+/// so a crash or a full disk never leaves it truncated.
+fn write_atomically(
+    path: &Path,
+    write: impl FnOnce(&mut File) -> io::Result<()>,
+) -> Result<(), Error> {
+    let parent = path.parent().unwrap_or(Path::new("."));
+    let mut temp_file = NamedTempFile::new_in(parent)?;
+    write(temp_file.as_file_mut())?;
+    temp_file.as_file().sync_all()?;
+    temp_file.persist(path).map_err(|e| e.error)?;
+    Ok(())
+}
+
+/// Reads the manifest of an installation.
+fn read_manifest(toolchain_dir: &Path) -> Result<serde_json::Value, Error> {
+    let manifest_path = toolchain_dir.join(MANIFEST_FILE);
+    let manifest = read(&manifest_path)
+        .map_err(|_| Error::MissingManifest(manifest_path.display().to_string()))?;
+    serde_json::from_slice(&manifest).map_err(|_| Error::SerializeJson)
+}
+
+/// Returns when the toolchains were last installed or updated, as recorded in the manifest.
+///
+/// Manifests written by older versions of espup don't record it.
+pub fn installed_at(toolchain_dir: &Path) -> Result<Option<SystemTime>, Error> {
+    Ok(read_manifest(toolchain_dir)?["installed_at"]
+        .as_u64()
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)))
+}
+
+/// Describes when, and with which versions of espup, the toolchains were installed and last
+/// updated, as recorded in the manifest, e.g. for bug reports.
+///
+/// Manifests written by older versions of espup don't record it.
+pub fn installation_summary(toolchain_dir: &Path) -> Result<Option<String>, Error> {
+    let manifest = read_manifest(toolchain_dir)?;
+    let (Some(installed_at), Some(installed_with_version)) = (
+        manifest["installed_at"].as_u64(),
+        manifest["installed_with_version"].as_str(),
+    ) else {
+        return Ok(None);
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let days = now.saturating_sub(installed_at) / (24 * 60 * 60);
+    let mut summary = format!(
+        "installed {days} days ago (at {installed_at}) with espup {installed_with_version}"
+    );
+    if let Some(updated_with_version) = manifest["updated_with_version"].as_str() {
+        summary.push_str(&format!(", last updated with espup {updated_with_version}"));
+    }
+    Ok(Some(summary))
+}
+
+/// Records a shell profile modified to source the export file of the installation, so
+/// `uninstall` only edits the profiles espup wrote to.
+pub fn record_profile(toolchain_dir: &Path, profile: &Path) -> Result<(), Error> {
+    let mut manifest = read_manifest(toolchain_dir)?;
+    let mut profiles = recorded_profiles(&manifest);
+    let profile = profile.to_string_lossy().to_string();
+    if profiles.contains(&profile) {
+        return Ok(());
+    }
+    profiles.push(profile);
+    manifest["profiles"] = json!(profiles);
+    let contents = serde_json::to_string_pretty(&manifest).map_err(|_| Error::SerializeJson)?;
+    write_atomically(&toolchain_dir.join(MANIFEST_FILE), |file| {
+        file.write_all(contents.as_bytes())
+    })
+}
+
+/// Returns the shell profiles modified to source the export file of the installation, as
+/// recorded in the manifest.
+///
+/// Manifests written by older versions of espup don't record them.
+pub fn modified_profiles(toolchain_dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    Ok(recorded_profiles(&read_manifest(toolchain_dir)?)
+        .into_iter()
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Returns the shell profiles recorded in a manifest.
+fn recorded_profiles(manifest: &serde_json::Value) -> Vec<String> {
+    manifest["profiles"]
+        .as_array()
+        .map(|profiles| {
+            profiles
+                .iter()
+                .filter_map(|profile| profile.as_str().map(ToString::to_string))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the options the toolchains were installed with, as recorded in the manifest.
+///
+/// Manifests written by older versions of espup don't record them.
+pub fn installed_options(toolchain_dir: &Path) -> Result<Option<serde_json::Value>, Error> {
+    let options = &read_manifest(toolchain_dir)?["options"];
+    Ok(options.is_object().then(|| options.clone()))
+}
+
+/// Returns the version of an installed component, as recorded in the manifest.
+pub fn installed_version(toolchain_dir: &Path, component: &str) -> Result<Option<String>, Error> {
+    let manifest = read_manifest(toolchain_dir)?;
+    Ok(manifest["components"].as_array().and_then(|components| {
+        components
+            .iter()
+            .find(|c| c["name"] == component)
+            .and_then(|c| c["version"].as_str())
+            .map(str::to_string)
+    }))
+}
+
+/// Returns the name and version of every installed component, as recorded in the manifest.
+pub fn installed_components(toolchain_dir: &Path) -> Result<Vec<(String, String)>, Error> {
+    let manifest = read_manifest(toolchain_dir)?;
+    Ok(manifest["components"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|component| {
+            Some((
+                component["name"].as_str()?.to_string(),
+                component["version"].as_str()?.to_string(),
+            ))
+        })
+        .collect())
+}
+
+/// Returns the URLs an installed component was downloaded from, as recorded in the manifest.
+pub fn installed_sources(toolchain_dir: &Path, component: &str) -> Result<Vec<String>, Error> {
+    let manifest = read_manifest(toolchain_dir)?;
+    Ok(manifest["components"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|c| c["name"] == component)
+        .filter_map(|c| c["sources"].as_array())
+        .flatten()
+        .filter_map(|source| source["url"].as_str().map(str::to_string))
+        .collect())
+}
+
+/// Returns the host triple an installation was made for, as recorded in the manifest.
+pub fn installed_host_triple(toolchain_dir: &Path) -> Result<Option<HostTriple>, Error> {
+    Ok(read_manifest(toolchain_dir)?["host_triple"]
+        .as_str()
+        .and_then(|host_triple| HostTriple::from_str(host_triple).ok()))
+}
+
+/// Returns whether an installation was made for the host it runs on, as recorded in the manifest.
+///
+/// Manifests written by older versions of espup don't record it.
+pub fn installed_native(toolchain_dir: &Path) -> Result<Option<bool>, Error> {
+    Ok(read_manifest(toolchain_dir)?["native"].as_bool())
+}
+
+/// Returns the targets of an installation, as recorded in the manifest.
+///
+/// Manifests written by older versions of espup don't record them.
+pub fn installed_targets(toolchain_dir: &Path) -> Result<Option<HashSet<Target>>, Error> {
+    Ok(read_manifest(toolchain_dir)?["targets"]
+        .as_array()
+        .map(|targets| {
+            targets
+                .iter()
+                .filter_map(|target| Target::from_str(target.as_str()?).ok())
+                .collect()
+        }))
+}
+
+/// Returns the files of the installation that changed since the manifest was written, as the
+/// name of their component and a message naming the file.
+///
+/// Every file is checked with the algorithm its checksum was recorded with. Installations
+/// without a manifest, or with one written by older versions of espup, have no recorded files
+/// and are never reported as changed.
+pub fn changed_files(toolchain_dir: &Path) -> Result<Vec<(String, String)>, Error> {
+    let manifest = match read_manifest(toolchain_dir) {
+        Ok(manifest) => manifest,
+        Err(Error::MissingManifest(_)) => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut changes = Vec::new();
+    for component in manifest["components"].as_array().into_iter().flatten() {
+        let name = component["name"].as_str().unwrap_or_default();
+        for file in component["files"].as_array().into_iter().flatten() {
+            let Some(relative_path) = file["path"].as_str() else {
+                continue;
+            };
+            let Some((algo, expected)) = ChecksumAlgo::iter()
+                .find_map(|algo| Some((algo, file[algo.to_string()].as_str()?)))
+            else {
+                continue;
+            };
+            let path = toolchain_dir.join(relative_path);
+            let change = match algo.checksum_file(&path) {
+                Ok(checksum) if checksum == expected => continue,
+                Ok(_) => format!("{name}: '{}' was modified", path.display()),
+                Err(_) => format!("{name}: '{}' is missing", path.display()),
+            };
+            changes.push((name.to_string(), change));
+        }
+    }
+    Ok(changes)
+}
+
+/// Returns a stable key that changes whenever the installed toolchains change.
+///
+/// Only the host triple, targets, and the versions and sources of the components are part of the
+/// key. Checksums are left out, as they are missing for reused components and depend on the
+/// algorithm they were computed with.
+pub fn cache_key(toolchain_dir: &Path) -> Result<String, Error> {
+    let manifest = read_manifest(toolchain_dir)?;
+    let components: Vec<serde_json::Value> = manifest["components"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|component| {
+            let sources: Vec<&serde_json::Value> = component["sources"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(|source| &source["url"])
+                .collect();
+            json!({
+                "name": component["name"],
+                "version": component["version"],
+                "sources": sources,
+            })
+        })
+        .collect();
+    let key = json!({
+        "host_triple": manifest["host_triple"],
+        "targets": manifest["targets"],
+        "components": components,
+    });
+    Ok(format!("{:x}", Sha256::digest(key.to_string())))
+}
+
+/// Finds the toolchain directories in `dir` espup started installing into but never wrote the
+/// manifest of, i.e. left behind by interrupted installations.
+///
+/// Toolchains installed by versions of espup that didn't write the marker are never reported.
+pub fn untracked_toolchains(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut toolchains: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_dir()
+                && !path.join(MANIFEST_FILE).exists()
+                && path.join(INSTALLING_MARKER_FILE).exists()
+        })
+        .collect();
+    toolchains.sort();
+    toolchains
+}
+
+/// Returns the size, in bytes, of a file or directory, without following symlinks.
+pub fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = path.symlink_metadata() else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    read_dir(path)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| disk_usage(&entry.path()))
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        host_triple::HostTriple,
+        targets::Target,
+        toolchain::{
+            InstalledComponent,
+            manifest::{
+                ChecksumAlgo, MANIFEST_FILE, cache_key, changed_files, disk_usage,
+                installation_summary, installed_at, installed_options, installed_sources,
+                installed_targets, installed_version, mark_installing, modified_profiles,
+                record_checksum, record_profile, untracked_toolchains, write_atomically,
+                write_manifest,
+            },
+            settings::{Settings, sync_scope},
+        },
+    };
+    use serde_json::json;
+    use std::{
+        collections::HashSet,
+        fs::{create_dir_all, read_dir, read_to_string, remove_file, write},
+        io::{self, Write},
+        path::{Path, PathBuf},
+    };
+    use strum::IntoEnumIterator;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_untracked_toolchains() {
+        let temp_dir = TempDir::new().unwrap();
+        let toolchains_dir = temp_dir.path();
+        // Tracked installation
+        create_dir_all(toolchains_dir.join("esp").join("xtensa-esp-elf")).unwrap();
+        write(toolchains_dir.join("esp").join(MANIFEST_FILE), "{}").unwrap();
+        // Interrupted installation
+        let interrupted = toolchains_dir.join("esp-old");
+        mark_installing(&interrupted).unwrap();
+        create_dir_all(interrupted.join("xtensa-esp32-elf-clang")).unwrap();
+        write(
+            interrupted
+                .join("xtensa-esp32-elf-clang")
+                .join("libclang.so"),
+            [0; 100],
+        )
+        .unwrap();
+        // Installation of another name by an older espup, without the marker
+        create_dir_all(toolchains_dir.join("esp-1.80").join("xtensa-esp-elf")).unwrap();
+        // Toolchain installed by rustup
+        create_dir_all(
+            toolchains_dir
+                .join("stable-x86_64-unknown-linux-gnu")
+                .join("bin"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            untracked_toolchains(toolchains_dir),
+            vec![interrupted.clone()]
+        );
+        assert_eq!(disk_usage(&interrupted), 100);
+        assert!(untracked_toolchains(&toolchains_dir.join("missing")).is_empty());
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(cache_key(temp_dir.path()).is_err());
+
+        let url = "https://example.com/rust-1.88.0.0.tar.xz".to_string();
+        let mut components = vec![InstalledComponent {
+            name: "Xtensa Rust".to_string(),
+            version: "1.88.0.0".to_string(),
+            sources: vec![url.clone()],
+            files: Vec::new(),
+        }];
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let targets = HashSet::from([Target::ESP32, Target::ESP32C3]);
+        // The checksums are recorded by the downloads of the installation
+        sync_scope(Settings::default(), || {
+            record_checksum(&url, "abcd".to_string());
+            write_manifest(
+                temp_dir.path(),
+                &host_triple,
+                true,
+                &targets,
+                &components,
+                &json!({ "std": true }),
+            )
+            .unwrap();
+        });
+        let key = cache_key(temp_dir.path()).unwrap();
+        assert_eq!(
+            installed_version(temp_dir.path(), "Xtensa Rust").unwrap(),
+            Some("1.88.0.0".to_string())
+        );
+        assert_eq!(installed_version(temp_dir.path(), "LLVM").unwrap(), None);
+        assert_eq!(
+            installed_sources(temp_dir.path(), "Xtensa Rust").unwrap(),
+            vec!["https://example.com/rust-1.88.0.0.tar.xz".to_string()]
+        );
+        assert!(installed_at(temp_dir.path()).unwrap().is_some());
+        assert_eq!(
+            installed_options(temp_dir.path()).unwrap(),
+            Some(json!({ "std": true }))
+        );
+        assert_eq!(
+            installed_targets(temp_dir.path()).unwrap(),
+            Some(targets.clone())
+        );
+
+        // Same installation, same key
+        write_manifest(
+            temp_dir.path(),
+            &host_triple,
+            true,
+            &targets,
+            &components,
+            &json!({}),
+        )
+        .unwrap();
+        assert_eq!(cache_key(temp_dir.path()).unwrap(), key);
+
+        // Reused components, without checksums, and other checksum algorithms keep the key
+        let manifest = read_to_string(temp_dir.path().join(MANIFEST_FILE)).unwrap();
+        for modified in [
+            manifest.replace(r#""abcd""#, "null"),
+            manifest.replace("sha256", "blake3"),
+        ] {
+            write(temp_dir.path().join(MANIFEST_FILE), modified).unwrap();
+            assert_eq!(cache_key(temp_dir.path()).unwrap(), key);
+        }
+
+        // Different version, different key
+        components[0].version = "1.89.0.0".to_string();
+        write_manifest(
+            temp_dir.path(),
+            &host_triple,
+            true,
+            &targets,
+            &components,
+            &json!({}),
+        )
+        .unwrap();
+        assert_ne!(cache_key(temp_dir.path()).unwrap(), key);
+    }
+
+    #[test]
+    fn test_record_profile() {
+        let temp_dir = TempDir::new().unwrap();
+        let components = vec![InstalledComponent {
+            name: "Xtensa Rust".to_string(),
+            version: "1.88.0.0".to_string(),
+            sources: Vec::new(),
+            files: Vec::new(),
+        }];
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let targets = HashSet::from([Target::ESP32]);
+        let write = |components: &[InstalledComponent]| {
+            write_manifest(
+                temp_dir.path(),
+                &host_triple,
+                true,
+                &targets,
+                components,
+                &json!({}),
+            )
+            .unwrap();
+        };
+        assert!(record_profile(temp_dir.path(), Path::new("/home/user/.bashrc")).is_err());
+
+        write(&components);
+        assert!(modified_profiles(temp_dir.path()).unwrap().is_empty());
+        record_profile(temp_dir.path(), Path::new("/home/user/.bashrc")).unwrap();
+        record_profile(temp_dir.path(), Path::new("/home/user/.bashrc")).unwrap();
+        record_profile(temp_dir.path(), Path::new("/home/user/.zshrc")).unwrap();
+        let profiles = vec![
+            PathBuf::from("/home/user/.bashrc"),
+            PathBuf::from("/home/user/.zshrc"),
+        ];
+        assert_eq!(modified_profiles(temp_dir.path()).unwrap(), profiles);
+
+        // Updating keeps them, the profiles still source the export file
+        let mut components = components;
+        components[0].version = "1.89.0.0".to_string();
+        write(&components);
+        assert_eq!(modified_profiles(temp_dir.path()).unwrap(), profiles);
+    }
+
+    #[test]
+    fn test_installed_at() {
+        let temp_dir = TempDir::new().unwrap();
+        let manifest_path = temp_dir.path().join(MANIFEST_FILE);
+        let mut components = vec![InstalledComponent {
+            name: "Xtensa Rust".to_string(),
+            version: "1.88.0.0".to_string(),
+            sources: Vec::new(),
+            files: Vec::new(),
+        }];
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let targets = HashSet::from([Target::ESP32]);
+        let write_installed_at = |installed_at: u64| {
+            let mut manifest: serde_json::Value =
+                serde_json::from_str(&read_to_string(&manifest_path).unwrap()).unwrap();
+            manifest["installed_at"] = json!(installed_at);
+            manifest["installed_with_version"] = json!("0.1.0");
+            write(&manifest_path, manifest.to_string()).unwrap();
+        };
+        let secs = |dir| {
+            installed_at(dir)
+                .unwrap()
+                .unwrap()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+
+        // Reinstalling the same version keeps the time of the installation
+        write_manifest(
+            temp_dir.path(),
+            &host_triple,
+            true,
+            &targets,
+            &components,
+            &json!({}),
+        )
+        .unwrap();
+        write_installed_at(1000);
+        write_manifest(
+            temp_dir.path(),
+            &host_triple,
+            true,
+            &targets,
+            &components,
+            &json!({}),
+        )
+        .unwrap();
+        assert_eq!(secs(temp_dir.path()), 1000);
+        let summary = installation_summary(temp_dir.path()).unwrap().unwrap();
+        assert!(summary.contains("(at 1000) with espup 0.1.0"));
+        assert!(summary.ends_with(&format!(
+            "last updated with espup {}",
+            env!("CARGO_PKG_VERSION")
+        )));
+
+        // Updating to another version resets it
+        components[0].version = "1.89.0.0".to_string();
+        write_manifest(
+            temp_dir.path(),
+            &host_triple,
+            true,
+            &targets,
+            &components,
+            &json!({}),
+        )
+        .unwrap();
+        assert!(secs(temp_dir.path()) > 1000);
+        assert!(
+            installation_summary(temp_dir.path())
+                .unwrap()
+                .unwrap()
+                .starts_with("installed 0 days ago")
+        );
+    }
+
+    #[test]
+    fn test_changed_files() {
+        let temp_dir = TempDir::new().unwrap();
+        assert!(changed_files(temp_dir.path()).unwrap().is_empty());
+
+        let rustc = temp_dir.path().join("bin").join("rustc");
+        let cargo = temp_dir.path().join("bin").join("cargo");
+        create_dir_all(temp_dir.path().join("bin")).unwrap();
+        write(&rustc, "rustc").unwrap();
+        write(&cargo, "cargo").unwrap();
+        let components = vec![InstalledComponent {
+            name: "Xtensa Rust".to_string(),
+            version: "1.88.0.0".to_string(),
+            sources: Vec::new(),
+            files: vec![rustc.clone(), cargo.clone()],
+        }];
+        let host_triple = HostTriple::X86_64UnknownLinuxGnu;
+        let targets = HashSet::from([Target::ESP32, Target::ESP32C3]);
+        write_manifest(
+            temp_dir.path(),
+            &host_triple,
+            true,
+            &targets,
+            &components,
+            &json!({}),
+        )
+        .unwrap();
+        assert!(changed_files(temp_dir.path()).unwrap().is_empty());
+
+        write(&rustc, "modified").unwrap();
+        remove_file(&cargo).unwrap();
+        assert_eq!(
+            changed_files(temp_dir.path()).unwrap(),
+            vec![
+                (
+                    "Xtensa Rust".to_string(),
+                    format!("Xtensa Rust: '{}' was modified", rustc.display())
+                ),
+                (
+                    "Xtensa Rust".to_string(),
+                    format!("Xtensa Rust: '{}' is missing", cargo.display())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_checksum_algo() {
+        let temp_dir = TempDir::new().unwrap();
+        let file = temp_dir.path().join("abc");
+        write(&file, "abc").unwrap();
+        assert_eq!(
+            ChecksumAlgo::Sha256.checksum_file(&file).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            ChecksumAlgo::Sha1.checksum_file(&file).unwrap(),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            ChecksumAlgo::Blake3.checksum_file(&file).unwrap(),
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        for algo in ChecksumAlgo::iter() {
+            assert_eq!(algo.checksum_file(&file).unwrap().len(), algo.hex_len());
+        }
+
+        // Files are checked with the algorithm their checksum was recorded with
+        let manifest = json!({
+            "components": [{
+                "name": "LLVM",
+                "files": [{ "path": "abc", "sha1": "a9993e364706816aba3e25717850c26c9cd0d89d" }],
+            }],
+        });
+        write(temp_dir.path().join(MANIFEST_FILE), manifest.to_string()).unwrap();
+        assert!(changed_files(temp_dir.path()).unwrap().is_empty());
+        write(&file, "modified").unwrap();
+        assert_eq!(changed_files(temp_dir.path()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("manifest.json");
+        write_atomically(&path, |file| file.write_all(b"original")).unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "original");
+
+        // A failed write keeps the original contents, and leaves no temporary file behind
+        assert!(
+            write_atomically(&path, |file| {
+                file.write_all(b"trunc")?;
+                Err(io::ErrorKind::StorageFull.into())
+            })
+            .is_err()
+        );
+        assert_eq!(read_to_string(&path).unwrap(), "original");
+        assert_eq!(read_dir(temp_dir.path()).unwrap().count(), 1);
+    }
+}
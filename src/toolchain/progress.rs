@@ -0,0 +1,73 @@
+//! Installation progress notifications.
+
+use crate::{error::Error, toolchain::PROCESS_BARS};
+use indicatif::{ProgressBar, ProgressStyle};
+use log::info;
+use std::{collections::HashMap, sync::Mutex};
+
+/// Receives progress notifications while the toolchains are being installed.
+///
+/// Every method has an empty default implementation, so implementors only need to override the
+/// events they are interested in.
+pub trait ProgressReporter: Send + Sync {
+    /// Called when the installation of a component starts.
+    fn on_component_start(&self, _name: &str) {}
+    /// Called when the installation of a component finishes, successfully or not.
+    fn on_component_done(&self, _name: &str, _result: Result<(), &Error>) {}
+    /// Called when a download starts, `downloaded` is non-zero when resuming a partial download.
+    fn on_download_start(&self, _file_name: &str, _downloaded: u64) {}
+    /// Called when more bytes of a download are received.
+    fn on_download_progress(&self, _file_name: &str, _downloaded: u64, _total: Option<u64>) {}
+    /// Called when a download finishes, successfully or not.
+    fn on_download_done(&self, _file_name: &str, _success: bool) {}
+}
+
+/// Progress reporter that ignores every notification.
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {}
+
+/// Progress reporter used by the command line interface, showing a progress bar per download.
+#[derive(Default)]
+pub struct DefaultProgressReporter {
+    bars: Mutex<HashMap<String, ProgressBar>>,
+}
+
+impl ProgressReporter for DefaultProgressReporter {
+    fn on_download_start(&self, file_name: &str, downloaded: u64) {
+        let style = ProgressStyle::with_template(
+            "[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}",
+        )
+        .unwrap()
+        .progress_chars("##-");
+        let bar = PROCESS_BARS.add(ProgressBar::no_length());
+        bar.set_style(style);
+        bar.set_message(file_name.to_string());
+        bar.set_position(downloaded);
+        self.bars.lock().unwrap().insert(file_name.to_string(), bar);
+    }
+
+    fn on_download_progress(&self, file_name: &str, downloaded: u64, total: Option<u64>) {
+        if let Some(bar) = self.bars.lock().unwrap().get(file_name) {
+            if let Some(total) = total {
+                bar.set_length(total);
+            }
+            bar.set_position(downloaded);
+        }
+    }
+
+    fn on_download_done(&self, file_name: &str, success: bool) {
+        let mut bars = self.bars.lock().unwrap();
+        if let Some(bar) = bars.remove(file_name) {
+            if success {
+                bar.finish_with_message(format!("{file_name} download complete"));
+            } else {
+                bar.finish_with_message(format!("{file_name} download failed"));
+            }
+        }
+        if bars.is_empty() {
+            PROCESS_BARS.clear().unwrap();
+            info!("All downloads complete");
+        }
+    }
+}
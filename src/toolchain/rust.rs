@@ -1,44 +1,68 @@
 //! Xtensa Rust Toolchain source and installation tools.
 
 use crate::{
+    env::{get_espup_dir, get_home_dir},
     error::Error,
     host_triple::HostTriple,
     toolchain::{
-        Installable, download_file,
+        ComponentStatus, Installable, PartialDir, download_file,
+        extra::EXTRA_ARTIFACTS_DIR,
         gcc::{RISCV_GCC, XTENSA_GCC},
-        github_query,
+        github_query, github_query_all, is_cross_host, is_refresh,
         llvm::CLANG_NAME,
+        manifest::installed_version,
+        progress::ProgressReporter,
+        redact_url, settings, version_api_fallbacks,
     },
 };
 use async_trait::async_trait;
-use directories::BaseDirs;
+use clap::ValueEnum;
 use log::{debug, info, warn};
 use miette::Result;
 use regex::Regex;
-#[cfg(unix)]
-use std::fs::create_dir_all;
 use std::{
     env,
+    ffi::OsStr,
     fmt::Debug,
-    fs::read_dir,
+    fs::{create_dir_all, read_dir, read_to_string, write},
     io,
     path::{Path, PathBuf},
     process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
+use strum::Display;
+use tempfile::tempdir;
 #[cfg(unix)]
 use tempfile::tempdir_in;
 use tokio::fs::{remove_dir_all, remove_file};
 
 /// Xtensa Rust Toolchain repository
-const DEFAULT_XTENSA_RUST_REPOSITORY: &str =
+pub(crate) const DEFAULT_XTENSA_RUST_REPOSITORY: &str =
     "https://github.com/esp-rs/rust-build/releases/download";
 
+/// Rustup installer repository
+const RUSTUP_INIT_URL: &str = "https://static.rust-lang.org/rustup/dist";
+
 /// Xtensa Rust Toolchain API URL
 const XTENSA_RUST_LATEST_API_URL: &str =
     "https://api.github.com/repos/esp-rs/rust-build/releases/latest";
 const XTENSA_RUST_API_URL: &str =
     "https://api.github.com/repos/esp-rs/rust-build/releases?page=1&per_page=100";
 
+/// File of the espup directory caching the latest Xtensa Rust version.
+const LATEST_VERSION_CACHE_FILE: &str = "latest-xtensa-rust-version";
+/// How long the cached latest Xtensa Rust version is used for.
+const LATEST_VERSION_CACHE_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Estimated size of the Xtensa Rust toolchain, including its compressed download.
+const XTENSA_RUST_REQUIRED_SPACE: u64 = 2_500_000_000;
+/// Estimated size of a minimal Rust toolchain with the RISC-V targets.
+const RISCV_TARGET_REQUIRED_SPACE: u64 = 800_000_000;
+
+/// Versions that select the latest Xtensa Rust release, like omitting the version.
+const LATEST_VERSION_ALIASES: [&str; 2] = ["latest", "stable"];
+
 /// Xtensa Rust Toolchain version regex.
 pub const RE_EXTENDED_SEMANTIC_VERSION: &str = r"^(?P<major>0|[1-9]\d*)\.(?P<minor>0|[1-9]\d*)\.(?P<patch>0|[1-9]\d*)\.(?P<subpatch>0|[1-9]\d*)$";
 /// Matches version strings with 1-4 parts.
@@ -49,6 +73,8 @@ pub const RE_ANY_SEMANTIC_VERSION: &str =
 pub struct XtensaRust {
     /// Path to the cargo home directory.
     pub cargo_home: PathBuf,
+    /// Whether the toolchain is downloaded from a custom URL instead of its release.
+    pub custom_dist: bool,
     /// Xtensa Rust toolchain file.
     pub dist_file: String,
     /// Xtensa Rust toolchain URL.
@@ -71,13 +97,95 @@ pub struct XtensaRust {
     pub version: String,
 }
 
+/// Release notes of an Xtensa Rust version.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReleaseNotes {
+    /// Xtensa Rust version.
+    pub version: String,
+    /// Release notes, as published on GitHub.
+    pub notes: String,
+}
+
 impl XtensaRust {
     /// Get the latest version of Xtensa Rust toolchain.
+    ///
+    /// The version is cached for [`LATEST_VERSION_CACHE_TTL`], to avoid querying the GitHub API
+    /// on every invocation, unless `--refresh` is used.
     pub async fn get_latest_version() -> Result<String, Error> {
+        let cache_file = get_espup_dir().map(|dir| dir.join(LATEST_VERSION_CACHE_FILE));
+        if let Ok(cache_file) = &cache_file
+            && !is_refresh()
+            && let Some(version) = read_cached_version(cache_file, LATEST_VERSION_CACHE_TTL)
+        {
+            debug!("Using cached latest Xtensa Rust version: {version}");
+            return Ok(version);
+        }
+
+        let version = Self::query_latest_version().await?;
+        if let Ok(cache_file) = &cache_file
+            && let Err(e) = write_cached_version(cache_file, &version)
+        {
+            debug!("Failed to cache the latest Xtensa Rust version: {e}");
+        }
+        Ok(version)
+    }
+
+    /// Queries the latest version of Xtensa Rust toolchain from the GitHub API, or from the
+    /// fallback endpoints, in order, if it fails.
+    async fn query_latest_version() -> Result<String, Error> {
+        let error = match Self::query_github_latest_version().await {
+            Ok(version) => return Ok(version),
+            Err(error) => error,
+        };
+        let fallbacks = version_api_fallbacks();
+        if fallbacks.is_empty() {
+            return Err(error);
+        }
+        warn!("Failed to query the latest Xtensa Rust version from the GitHub API: {error}");
+        for url in fallbacks {
+            let query_url = url.clone();
+            let result = settings::spawn_blocking(move || github_query(&query_url))
+                .await
+                .map_err(|e| {
+                    Error::GithubConnectivityError(format!("Failed to query '{url}': {e}"))
+                })
+                .and_then(|json| Self::parse_latest_release(&json?));
+            match result {
+                Ok(version) => {
+                    debug!(
+                        "Latest Xtensa Rust version resolved from '{}': {version}",
+                        redact_url(&url)
+                    );
+                    return Ok(version);
+                }
+                Err(e) => warn!(
+                    "Failed to query the latest Xtensa Rust version from '{}': {e}",
+                    redact_url(&url)
+                ),
+            }
+        }
+        Err(error)
+    }
+
+    /// Parses the version of a response of the latest release API, e.g. `{"tag_name": "v1.88.0.0"}`.
+    fn parse_latest_release(json: &serde_json::Value) -> Result<String, Error> {
+        lazy_static::lazy_static! {
+            static ref RE_EXTENDED: Regex = Regex::new(RE_EXTENDED_SEMANTIC_VERSION).unwrap();
+        };
+        let tag = json["tag_name"].as_str().ok_or(Error::SerializeJson)?;
+        let version = tag.strip_prefix('v').unwrap_or(tag);
+        if !RE_EXTENDED.is_match(version) {
+            return Err(Error::InvalidVersion(version.to_string()));
+        }
+        Ok(version.to_string())
+    }
+
+    /// Queries the latest version of Xtensa Rust toolchain from the GitHub API.
+    async fn query_github_latest_version() -> Result<String, Error> {
         debug!("Querying latest Xtensa Rust version from GitHub API");
 
         // First, handle the spawn_blocking result
-        let query_result = tokio::task::spawn_blocking(|| github_query(XTENSA_RUST_LATEST_API_URL))
+        let query_result = settings::spawn_blocking(|| github_query(XTENSA_RUST_LATEST_API_URL))
             .await
             .map_err(|e| {
                 Error::GithubConnectivityError(format!("Failed to query GitHub API: {e}"))
@@ -95,18 +203,79 @@ impl XtensaRust {
 
         // Validate the version format - handle both spawning and parsing errors
         let parse_task =
-            tokio::task::spawn_blocking(move || Self::find_latest_version_on_github(&version))
+            settings::spawn_blocking(move || Self::find_latest_version_on_github(&version))
                 .await
                 .map_err(|_| Error::SerializeJson)?;
 
         let validated_version = parse_task?;
 
-        debug!("Latest Xtensa Rust version: {validated_version}");
+        debug!("Latest Xtensa Rust version resolved from the GitHub API: {validated_version}");
         Ok(validated_version)
     }
 
+    /// Retrieves the release notes of the versions newer than `since`, up to `latest`, newest first.
+    pub async fn release_notes(since: &str, latest: &str) -> Result<Vec<ReleaseNotes>, Error> {
+        let releases = settings::spawn_blocking(|| github_query_all(XTENSA_RUST_API_URL))
+            .await
+            .map_err(|e| {
+                Error::GithubConnectivityError(format!("Failed to query GitHub API: {e}"))
+            })??;
+        Ok(Self::select_release_notes(&releases, since, latest))
+    }
+
+    /// Selects the release notes of the versions newer than `since`, up to `latest`, newest first.
+    ///
+    /// Releases whose tag is not an extended semantic version, e.g. release candidates, are
+    /// ignored.
+    fn select_release_notes(
+        releases: &[serde_json::Value],
+        since: &str,
+        latest: &str,
+    ) -> Vec<ReleaseNotes> {
+        let re_extended = Regex::new(RE_EXTENDED_SEMANTIC_VERSION).unwrap();
+        let parse = |version: &str| -> Option<Vec<u64>> {
+            re_extended
+                .is_match(version)
+                .then(|| version.split('.').map(|n| n.parse().unwrap()).collect())
+        };
+        let (Some(since), Some(latest)) = (parse(since), parse(latest)) else {
+            return Vec::new();
+        };
+
+        let mut notes: Vec<(Vec<u64>, ReleaseNotes)> = releases
+            .iter()
+            .filter_map(|release| {
+                let version = release["tag_name"].as_str()?.trim_start_matches('v');
+                let parsed = parse(version)?;
+                (parsed > since && parsed <= latest).then(|| {
+                    let notes = ReleaseNotes {
+                        version: version.to_string(),
+                        notes: release["body"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .trim()
+                            .to_string(),
+                    };
+                    (parsed, notes)
+                })
+            })
+            .collect();
+        notes.sort_by(|(a, _), (b, _)| b.cmp(a));
+        notes.into_iter().map(|(_, notes)| notes).collect()
+    }
+
     /// Create a new instance.
-    pub fn new(toolchain_version: &str, host_triple: &HostTriple, toolchain_path: &Path) -> Self {
+    pub fn new(
+        toolchain_version: &str,
+        host_triple: &HostTriple,
+        toolchain_path: &Path,
+    ) -> Result<Self, Error> {
+        if host_triple.is_musl() {
+            return Err(Error::NoAssetForHost {
+                component: "Xtensa Rust".to_string(),
+                host_triple: host_triple.to_string(),
+            });
+        }
         let artifact_extension = get_artifact_extension(host_triple);
         let version = toolchain_version.to_string();
         let dist = format!("rust-{version}-{host_triple}");
@@ -118,12 +287,13 @@ impl XtensaRust {
         let src_dist_file = format!("{src_dist}.{artifact_extension}");
         #[cfg(unix)]
         let src_dist_url = format!("{DEFAULT_XTENSA_RUST_REPOSITORY}/v{version}/{src_dist_file}");
-        let cargo_home = get_cargo_home();
-        let rustup_home = get_rustup_home();
+        let cargo_home = get_cargo_home()?;
+        let rustup_home = get_rustup_home()?;
         let toolchain_destination = toolchain_path.to_path_buf();
 
-        Self {
+        Ok(Self {
             cargo_home,
+            custom_dist: false,
             dist_file,
             dist_url,
             host_triple: host_triple.to_string(),
@@ -135,7 +305,46 @@ impl XtensaRust {
             src_dist_url,
             toolchain_destination,
             version,
+        })
+    }
+
+    /// Downloads the toolchain from a custom URL, e.g. an unreleased build of a rust-build tag,
+    /// instead of its release. The rust-src component is expected next to it.
+    pub fn with_dist_url(mut self, url: &str) -> Self {
+        self.dist_url = url.to_string();
+        self.dist_file = url.rsplit('/').next().unwrap_or_default().to_string();
+        #[cfg(unix)]
+        {
+            let base_url = url.rsplit_once('/').map_or(url, |(base_url, _)| base_url);
+            self.src_dist_url = format!("{base_url}/{}", self.src_dist_file);
         }
+        self.custom_dist = true;
+        self
+    }
+
+    /// Retrieves the version of the toolchain from the file name of a custom URL, e.g.
+    /// `rust-1.88.0.0-x86_64-unknown-linux-gnu.tar.xz`.
+    pub fn version_from_url(url: &str) -> Result<String, Error> {
+        let file_name = url.split(['?', '#']).next().unwrap_or_default();
+        let file_name = file_name.rsplit('/').next().unwrap_or_default();
+        let re = Regex::new(r"^rust-(\d+\.\d+\.\d+\.\d+)-").unwrap();
+        re.captures(file_name)
+            .map(|captures| captures[1].to_string())
+            .ok_or_else(|| Error::InvalidToolchainUrl(url.to_string()))
+    }
+
+    /// Returns true if the version is an alias of the latest release, e.g. `latest`.
+    pub fn is_latest_alias(version: &str) -> bool {
+        LATEST_VERSION_ALIASES.contains(&version)
+    }
+
+    /// Returns true if the version is a complete release version, e.g. `1.88.0.0`, which does not
+    /// need to be resolved against the list of releases.
+    pub fn is_exact_version(version: &str) -> bool {
+        lazy_static::lazy_static! {
+            static ref RE_EXTENDED: Regex = Regex::new(RE_EXTENDED_SEMANTIC_VERSION).unwrap();
+        };
+        RE_EXTENDED.is_match(version)
     }
 
     /// Retrieves the latest version of the Xtensa toolchain.
@@ -143,10 +352,10 @@ impl XtensaRust {
     /// Note that this function issues a GitHub API request to retrieve the latest version of the Xtensa toolchain.
     pub fn find_latest_version_on_github(version: &str) -> Result<String, Error> {
         debug!("Parsing Xtensa Rust version: {version}");
-        let json = github_query(XTENSA_RUST_API_URL)?;
+        let releases = github_query_all(XTENSA_RUST_API_URL)?;
 
         let mut candidates: Vec<String> = Vec::new();
-        for release in json.as_array().unwrap() {
+        for release in &releases {
             candidates.push(release["tag_name"].to_string().replace(['\"', 'v'], ""));
         }
 
@@ -201,6 +410,19 @@ impl XtensaRust {
         }
     }
 
+    /// Verifies the Xtensa Rust toolchain, if installed.
+    pub fn verify(toolchain_path: &Path) -> Option<ComponentStatus> {
+        let bin_path = toolchain_path.join("bin");
+        if !bin_path.exists() {
+            return None;
+        }
+        let binaries: Vec<PathBuf> = ["rustc", "cargo"]
+            .iter()
+            .map(|binary| bin_path.join(format!("{binary}{}", env::consts::EXE_SUFFIX)))
+            .collect();
+        Some(ComponentStatus::check(&binaries))
+    }
+
     /// Removes the Xtensa Rust toolchain.
     pub async fn uninstall(toolchain_path: &Path) -> Result<(), Error> {
         info!("Uninstalling Xtensa Rust toolchain");
@@ -211,6 +433,7 @@ impl XtensaRust {
             if !entry_name.contains(RISCV_GCC)
                 && !entry_name.contains(XTENSA_GCC)
                 && !entry_name.contains(CLANG_NAME)
+                && !entry_path.ends_with(EXTRA_ARTIFACTS_DIR)
             {
                 if entry_path.is_dir() {
                     remove_dir_all(Path::new(&entry_name))
@@ -227,47 +450,62 @@ impl XtensaRust {
 
 #[async_trait]
 impl Installable for XtensaRust {
-    async fn install(&self) -> Result<Vec<String>, Error> {
+    async fn install(&self, reporter: Arc<dyn ProgressReporter>) -> Result<Vec<String>, Error> {
         if self.toolchain_destination.exists() {
-            let toolchain_name = format!(
-                "+{}",
-                self.toolchain_destination
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap(),
-            );
-            let rustc_version = Command::new("rustc")
-                .args([&toolchain_name, "--version"])
-                .stdout(Stdio::piped())
-                .output()?;
-            let output = String::from_utf8_lossy(&rustc_version.stdout);
-            if rustc_version.status.success() && output.contains(&self.version) {
+            let reusable = if self.custom_dist {
+                // Custom builds may share the version of a release, always reinstall them
+                false
+            } else if is_cross_host() {
+                // Toolchains for other hosts can't be executed, rely on the manifest instead
+                installed_version(&self.toolchain_destination, &self.name())
+                    .ok()
+                    .flatten()
+                    .is_some_and(|version| version == self.version)
+            } else {
+                let toolchain_name = format!(
+                    "+{}",
+                    self.toolchain_destination
+                        .file_name()
+                        .unwrap()
+                        .to_str()
+                        .unwrap(),
+                );
+                let rustc_version = rustup_command("rustc")
+                    .args([&toolchain_name, "--version"])
+                    .stdout(Stdio::piped())
+                    .output()?;
+                let output = String::from_utf8_lossy(&rustc_version.stdout);
+                if !rustc_version.status.success() {
+                    warn!("Failed to detect version of Xtensa Rust, reinstalling it");
+                }
+                rustc_version.status.success() && output.contains(&self.version)
+            };
+            if reusable {
                 warn!(
                     "Previous installation of Xtensa Rust {} exists in: '{}'. Reusing this installation",
                     &self.version,
                     &self.toolchain_destination.display()
                 );
                 return Ok(vec![]);
-            } else {
-                if !rustc_version.status.success() {
-                    warn!("Failed to detect version of Xtensa Rust, reinstalling it");
-                }
-                Self::uninstall(&self.toolchain_destination).await?;
             }
+            Self::uninstall(&self.toolchain_destination).await?;
         }
 
         info!("Installing Xtensa Rust {} toolchain", self.version);
+        self.log_sources(&self.toolchain_destination);
 
         #[cfg(unix)]
         if cfg!(unix) {
-            let path = get_rustup_home().join("tmp");
+            let path = get_rustup_home()?.join("tmp");
             if !path.exists() {
                 info!("Creating directory: '{}'", path.display());
                 create_dir_all(&path)
                     .map_err(|_| Error::CreateDirectory(path.display().to_string()))?;
             }
             let tmp_dir = tempdir_in(path)?;
+            let _partial_dir = PartialDir::new(tmp_dir.path());
+            // install.sh populates the destination, which is left incomplete if interrupted
+            let _destination_partial_dir = PartialDir::new(&self.toolchain_destination);
             let tmp_dir_path = &tmp_dir.path().display().to_string();
 
             download_file(
@@ -276,6 +514,8 @@ impl Installable for XtensaRust {
                 tmp_dir_path,
                 true,
                 false,
+                None,
+                reporter.as_ref(),
             )
             .await?;
 
@@ -285,6 +525,8 @@ impl Installable for XtensaRust {
                 tmp_dir_path,
                 true,
                 false,
+                None,
+                reporter.as_ref(),
             )
             .await?;
 
@@ -341,6 +583,8 @@ impl Installable for XtensaRust {
                 &self.toolchain_destination.display().to_string(),
                 true,
                 true,
+                None,
+                reporter.as_ref(),
             )
             .await?;
         }
@@ -348,22 +592,72 @@ impl Installable for XtensaRust {
         Ok(vec![]) // No exports
     }
 
+    async fn uninstall(&self) -> Result<(), Error> {
+        XtensaRust::uninstall(&self.toolchain_destination).await
+    }
+
     fn name(&self) -> String {
         "Xtensa Rust".to_string()
     }
+
+    fn version(&self) -> String {
+        self.version.clone()
+    }
+
+    fn digest_files(&self) -> Vec<PathBuf> {
+        let bin_path = self.toolchain_destination.join("bin");
+        ["rustc", "cargo"]
+            .iter()
+            .map(|binary| bin_path.join(format!("{binary}{}", env::consts::EXE_SUFFIX)))
+            .collect()
+    }
+
+    fn required_space(&self) -> u64 {
+        XTENSA_RUST_REQUIRED_SPACE
+    }
+
+    fn sources(&self) -> Vec<String> {
+        if cfg!(windows) {
+            vec![self.dist_url.clone()]
+        } else {
+            vec![self.src_dist_url.clone(), self.dist_url.clone()]
+        }
+    }
+}
+
+/// Profiles rustup installs toolchains with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum RustupProfile {
+    /// Only `rustc`, `rust-std` and `cargo`.
+    #[default]
+    Minimal,
+    /// Also `rust-docs`, `rustfmt` and `clippy`.
+    Default,
+    /// Every available component.
+    Complete,
 }
 
 #[derive(Debug, Clone)]
 pub struct RiscVTarget {
-    /// Stable Rust toolchain version.
-    pub stable_version: String,
+    /// Stable Rust toolchain versions, tried in order until one can be installed.
+    pub stable_versions: Vec<String>,
+    /// Extra rustup components to install, besides `rust-src`.
+    pub components: Vec<String>,
+    /// Profile the stable toolchain is installed with.
+    pub profile: RustupProfile,
+    /// Stable Rust toolchain version that was installed.
+    installed_version: Arc<Mutex<Option<String>>>,
 }
 
 impl RiscVTarget {
     /// Create a crate instance.
-    pub fn new(stable_version: &str) -> Self {
+    pub fn new(stable_versions: &[String], components: &[String], profile: RustupProfile) -> Self {
         RiscVTarget {
-            stable_version: stable_version.to_string(),
+            stable_versions: stable_versions.to_vec(),
+            components: components.to_vec(),
+            profile,
+            installed_version: Arc::new(Mutex::new(None)),
         }
     }
 
@@ -371,7 +665,7 @@ impl RiscVTarget {
     pub fn uninstall(stable_version: &str) -> Result<(), Error> {
         info!("Uninstalling RISC-V target");
 
-        if !Command::new("rustup")
+        if !rustup_command("rustup")
             .args([
                 "target",
                 "remove",
@@ -393,42 +687,115 @@ impl RiscVTarget {
 
 #[async_trait]
 impl Installable for RiscVTarget {
-    async fn install(&self) -> Result<Vec<String>, Error> {
-        info!(
-            "Installing RISC-V Rust targets ('riscv32imc-unknown-none-elf', 'riscv32imac-unknown-none-elf' and 'riscv32imafc-unknown-none-elf') for '{}' toolchain",
-            &self.stable_version
-        );
-
-        if !Command::new("rustup")
-            .args([
-                "toolchain",
-                "install",
-                &self.stable_version,
-                "--profile",
-                "minimal",
-                "--component",
-                "rust-src",
-                "--target",
-                "riscv32imc-unknown-none-elf",
-                "--target",
-                "riscv32imac-unknown-none-elf",
-                "--target",
-                "riscv32imafc-unknown-none-elf",
-            ])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()?
-            .success()
-        {
-            return Err(Error::InstallRiscvTarget(self.stable_version.clone()));
+    async fn install(&self, _reporter: Arc<dyn ProgressReporter>) -> Result<Vec<String>, Error> {
+        let profile = self.profile.to_string();
+        for stable_version in &self.stable_versions {
+            info!(
+                "Installing RISC-V Rust targets ('riscv32imc-unknown-none-elf', 'riscv32imac-unknown-none-elf' and 'riscv32imafc-unknown-none-elf') for '{stable_version}' toolchain"
+            );
+            let output = rustup_command("rustup")
+                .args([
+                    "toolchain",
+                    "install",
+                    stable_version,
+                    "--profile",
+                    &profile,
+                    "--component",
+                    "rust-src",
+                    "--target",
+                    "riscv32imc-unknown-none-elf",
+                    "--target",
+                    "riscv32imac-unknown-none-elf",
+                    "--target",
+                    "riscv32imafc-unknown-none-elf",
+                ])
+                .args(
+                    self.components
+                        .iter()
+                        .flat_map(|component| ["--component", component]),
+                )
+                .stdout(Stdio::null())
+                .output()?;
+            if output.status.success() {
+                *self.installed_version.lock().unwrap() = Some(stable_version.clone());
+                return Ok(vec![]); // No exports
+            }
+            warn!(
+                "Failed to install the '{stable_version}' toolchain: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
         }
 
-        Ok(vec![]) // No exports
+        Err(Error::InstallRiscvTarget(self.stable_versions.join(", ")))
+    }
+
+    async fn uninstall(&self) -> Result<(), Error> {
+        RiscVTarget::uninstall(&self.version())
     }
 
     fn name(&self) -> String {
         "RISC-V Rust target".to_string()
     }
+
+    /// Returns the version that was installed, or the first candidate before the installation
+    fn version(&self) -> String {
+        match &*self.installed_version.lock().unwrap() {
+            Some(version) => version.clone(),
+            None => self.stable_versions[0].clone(),
+        }
+    }
+
+    fn required_space(&self) -> u64 {
+        RISCV_TARGET_REQUIRED_SPACE
+    }
+}
+
+/// Reads a cached version, if it was cached less than `ttl` ago.
+fn read_cached_version(cache_file: &Path, ttl: Duration) -> Option<String> {
+    let age = cache_file
+        .metadata()
+        .ok()?
+        .modified()
+        .ok()?
+        .elapsed()
+        .ok()?;
+    if age >= ttl {
+        return None;
+    }
+    let version = read_to_string(cache_file).ok()?.trim().to_string();
+    Regex::new(RE_EXTENDED_SEMANTIC_VERSION)
+        .unwrap()
+        .is_match(&version)
+        .then_some(version)
+}
+
+/// Caches a version.
+fn write_cached_version(cache_file: &Path, version: &str) -> Result<(), Error> {
+    if let Some(parent) = cache_file.parent() {
+        create_dir_all(parent)?;
+    }
+    write(cache_file, version)?;
+    Ok(())
+}
+
+/// Validates the name of a rustup component, e.g. `clippy`.
+pub fn parse_rust_component(component: &str) -> Result<String, Error> {
+    if !component.starts_with(|c: char| c.is_ascii_lowercase())
+        || !component
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(Error::InvalidRustComponent(component.to_string()));
+    }
+    Ok(component.to_string())
+}
+
+/// Validates a SHA-256, SHA-1 or BLAKE3 checksum, e.g. of `--toolchain-checksum`.
+pub fn parse_checksum(checksum: &str) -> Result<String, Error> {
+    if ![40, 64].contains(&checksum.len()) || !checksum.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(Error::InvalidChecksum(checksum.to_string()));
+    }
+    Ok(checksum.to_lowercase())
 }
 
 /// Gets the artifact extension based on the host architecture.
@@ -440,34 +807,61 @@ fn get_artifact_extension(host_triple: &HostTriple) -> &str {
 }
 
 /// Gets the default cargo home path.
-fn get_cargo_home() -> PathBuf {
-    PathBuf::from(env::var("CARGO_HOME").unwrap_or_else(|_e| {
-        format!(
-            "{}",
-            BaseDirs::new().unwrap().home_dir().join(".cargo").display()
-        )
-    }))
+fn get_cargo_home() -> Result<PathBuf, Error> {
+    match env::var("CARGO_HOME") {
+        Ok(cargo_home) => Ok(PathBuf::from(cargo_home)),
+        Err(_) => Ok(get_home_dir()?.join(".cargo")),
+    }
 }
 
 /// Gets the default rustup home path.
-pub fn get_rustup_home() -> PathBuf {
-    PathBuf::from(env::var("RUSTUP_HOME").unwrap_or_else(|_e| {
-        format!(
-            "{}",
-            BaseDirs::new()
-                .unwrap()
-                .home_dir()
-                .join(".rustup")
-                .display()
-        )
-    }))
+pub fn get_rustup_home() -> Result<PathBuf, Error> {
+    if let Some(rustup_home) = settings::current().settings.rustup_home.clone() {
+        return Ok(rustup_home);
+    }
+    match env::var("RUSTUP_HOME") {
+        Ok(rustup_home) => Ok(PathBuf::from(rustup_home)),
+        Err(_) => Ok(get_home_dir()?.join(".rustup")),
+    }
+}
+
+/// Creates a command running `program` with the rustup home of the settings, if any,
+/// and the `PATH` rustup was installed into by espup.
+pub(crate) fn rustup_command(program: impl AsRef<OsStr>) -> Command {
+    let mut command = Command::new(program);
+    let session = settings::current();
+    if let Some(rustup_home) = &session.settings.rustup_home {
+        command.env("RUSTUP_HOME", rustup_home);
+    }
+    // The program is searched in the `PATH` of the command
+    if let Some(path) = session.rustup_path.lock().unwrap().as_ref() {
+        command.env("PATH", path);
+    }
+    command
 }
 
-/// Checks if rustup is installed.
-pub async fn check_rust_installation() -> Result<(), Error> {
+/// Checks if rustup is installed, installing it when missing if `install_rustup` is set, along
+/// the stable toolchain as the default one if `default_toolchain` is set.
+pub async fn check_rust_installation(
+    install_rustup: bool,
+    default_toolchain: bool,
+    profile: RustupProfile,
+    host_triple: &HostTriple,
+    reporter: &dyn ProgressReporter,
+) -> Result<(), Error> {
     info!("Checking Rust installation");
 
-    if let Err(e) = Command::new("rustup")
+    match detect_rustup() {
+        Err(Error::MissingRust) if install_rustup => {
+            bootstrap_rustup(default_toolchain, host_triple, profile, reporter).await
+        }
+        result => result,
+    }
+}
+
+/// Checks if rustup can be executed.
+fn detect_rustup() -> Result<(), Error> {
+    if let Err(e) = rustup_command("rustup")
         .arg("--version")
         .stdout(Stdio::piped())
         .output()
@@ -482,16 +876,91 @@ pub async fn check_rust_installation() -> Result<(), Error> {
     Ok(())
 }
 
+/// Downloads and runs rustup-init non-interactively, without a default toolchain unless
+/// `default_toolchain` is set.
+async fn bootstrap_rustup(
+    default_toolchain: bool,
+    host_triple: &HostTriple,
+    profile: RustupProfile,
+    reporter: &dyn ProgressReporter,
+) -> Result<(), Error> {
+    info!("Installing rustup");
+    let tmp_dir = tempdir()?;
+    let file_name = format!("rustup-init{}", env::consts::EXE_SUFFIX);
+    let rustup_init = download_file(
+        format!("{RUSTUP_INIT_URL}/{host_triple}/{file_name}"),
+        &file_name,
+        &tmp_dir.path().display().to_string(),
+        false,
+        false,
+        None,
+        reporter,
+    )
+    .await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&rustup_init, PermissionsExt::from_mode(0o755))?;
+    }
+
+    let mut rustup_init = rustup_command(&rustup_init);
+    rustup_init.args(["-y", "--profile", &profile.to_string()]);
+    if !default_toolchain {
+        // The Xtensa Rust toolchain doesn't need a stable toolchain
+        debug!("Installing rustup without a default toolchain");
+        rustup_init.args(["--default-toolchain", "none"]);
+    }
+    if !rustup_init.stdout(Stdio::null()).status()?.success() {
+        return Err(Error::InstallRustup);
+    }
+
+    // rustup is installed into CARGO_HOME/bin, which is not in the PATH of this process yet
+    let cargo_bin = get_cargo_home()?.join("bin");
+    let path = env::var_os("PATH").unwrap_or_default();
+    let path = env::join_paths(std::iter::once(cargo_bin).chain(env::split_paths(&path)))
+        .map_err(|_| Error::InstallRustup)?;
+    *settings::current().rustup_path.lock().unwrap() = Some(path);
+
+    detect_rustup()
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{
+        host_triple::HostTriple,
         logging::initialize_logger,
-        toolchain::rust::{XtensaRust, get_cargo_home, get_rustup_home},
+        toolchain::rust::{
+            ReleaseNotes, XtensaRust, get_cargo_home, get_rustup_home, parse_checksum,
+            parse_rust_component, read_cached_version, write_cached_version,
+        },
     };
     use directories::BaseDirs;
-    use std::env;
+    use serde_json::json;
+    use std::{env, fs::write, path::Path, time::Duration};
     use tempfile::TempDir;
 
+    #[test]
+    fn test_cached_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let cache_file = temp_dir
+            .path()
+            .join("cache")
+            .join("latest-xtensa-rust-version");
+        let ttl = Duration::from_secs(600);
+        assert_eq!(read_cached_version(&cache_file, ttl), None);
+
+        write_cached_version(&cache_file, "1.88.0.0").unwrap();
+        assert_eq!(
+            read_cached_version(&cache_file, ttl),
+            Some("1.88.0.0".to_string())
+        );
+        // Expired
+        assert_eq!(read_cached_version(&cache_file, Duration::ZERO), None);
+        // Corrupted
+        write(&cache_file, "not a version").unwrap();
+        assert_eq!(read_cached_version(&cache_file, ttl), None);
+    }
+
     #[test]
     fn test_xtensa_rust_parse_version() {
         initialize_logger("debug");
@@ -545,6 +1014,104 @@ mod tests {
         assert!(XtensaRust::find_latest_version("1._.*.1", &candidates).is_err());
     }
 
+    #[test]
+    fn test_select_release_notes() {
+        let releases = vec![
+            json!({"tag_name": "v1.88.0.0", "body": "Rust 1.88"}),
+            json!({"tag_name": "v1.90.0.0-rc1", "body": "Release candidate"}),
+            json!({"tag_name": "v1.89.0.0", "body": " Rust 1.89\n"}),
+            json!({"tag_name": "v1.87.0.0", "body": "Rust 1.87"}),
+            json!({"tag_name": "v1.86.0.0"}),
+        ];
+        assert_eq!(
+            XtensaRust::select_release_notes(&releases, "1.87.0.0", "1.89.0.0"),
+            vec![
+                ReleaseNotes {
+                    version: "1.89.0.0".to_string(),
+                    notes: "Rust 1.89".to_string(),
+                },
+                ReleaseNotes {
+                    version: "1.88.0.0".to_string(),
+                    notes: "Rust 1.88".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            XtensaRust::select_release_notes(&releases, "1.85.0.0", "1.86.0.0")[0].notes,
+            ""
+        );
+        assert!(XtensaRust::select_release_notes(&releases, "1.89.0.0", "1.89.0.0").is_empty());
+        assert!(XtensaRust::select_release_notes(&releases, "1.87", "1.89.0.0").is_empty());
+    }
+
+    #[test]
+    fn test_parse_latest_release() {
+        assert_eq!(
+            XtensaRust::parse_latest_release(&json!({"tag_name": "v1.88.0.0"})).unwrap(),
+            "1.88.0.0"
+        );
+        assert_eq!(
+            XtensaRust::parse_latest_release(&json!({"tag_name": "1.88.0.0"})).unwrap(),
+            "1.88.0.0"
+        );
+        assert!(XtensaRust::parse_latest_release(&json!({"tag_name": "v1.88.0"})).is_err());
+        assert!(XtensaRust::parse_latest_release(&json!({"name": "v1.88.0.0"})).is_err());
+    }
+
+    #[test]
+    fn test_parse_rust_component() {
+        assert_eq!(parse_rust_component("clippy").unwrap(), "clippy");
+        assert_eq!(
+            parse_rust_component("llvm-tools-preview").unwrap(),
+            "llvm-tools-preview"
+        );
+        assert!(parse_rust_component("").is_err());
+        assert!(parse_rust_component("clippy rustfmt").is_err());
+        assert!(parse_rust_component("--force").is_err());
+    }
+
+    #[test]
+    fn test_version_from_url() {
+        let url = "https://github.com/esp-rs/rust-build/releases/download/v1.89.0.0-rc1/rust-1.89.0.0-x86_64-unknown-linux-gnu.tar.xz";
+        assert_eq!(XtensaRust::version_from_url(url).unwrap(), "1.89.0.0");
+        assert_eq!(
+            XtensaRust::version_from_url(
+                "https://example.com/rust-1.88.0.0-x86_64-pc-windows-msvc.zip?token=abc"
+            )
+            .unwrap(),
+            "1.88.0.0"
+        );
+        assert!(XtensaRust::version_from_url("https://example.com/toolchain.tar.xz").is_err());
+
+        let xtensa_rust = XtensaRust::new(
+            "1.89.0.0",
+            &HostTriple::X86_64UnknownLinuxGnu,
+            Path::new("esp"),
+        )
+        .unwrap()
+        .with_dist_url(url);
+        assert!(xtensa_rust.custom_dist);
+        assert_eq!(xtensa_rust.dist_url, url);
+        assert_eq!(
+            xtensa_rust.dist_file,
+            "rust-1.89.0.0-x86_64-unknown-linux-gnu.tar.xz"
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            xtensa_rust.src_dist_url,
+            "https://github.com/esp-rs/rust-build/releases/download/v1.89.0.0-rc1/rust-src-1.89.0.0.tar.xz"
+        );
+    }
+
+    #[test]
+    fn test_parse_checksum() {
+        let checksum = "AB".repeat(32);
+        assert_eq!(parse_checksum(&checksum).unwrap(), "ab".repeat(32));
+        assert_eq!(parse_checksum(&"ab".repeat(20)).unwrap(), "ab".repeat(20));
+        assert!(parse_checksum("abcd").is_err());
+        assert!(parse_checksum(&"zz".repeat(32)).is_err());
+    }
+
     #[test]
     fn test_get_cargo_home() {
         // No CARGO_HOME set
@@ -552,7 +1119,7 @@ mod tests {
             env::remove_var("CARGO_HOME");
         }
         assert_eq!(
-            get_cargo_home(),
+            get_cargo_home().unwrap(),
             BaseDirs::new().unwrap().home_dir().join(".cargo")
         );
         // CARGO_HOME set
@@ -561,7 +1128,7 @@ mod tests {
         unsafe {
             env::set_var("CARGO_HOME", cargo_home.to_str().unwrap());
         }
-        assert_eq!(get_cargo_home(), cargo_home);
+        assert_eq!(get_cargo_home().unwrap(), cargo_home);
     }
 
     #[test]
@@ -571,7 +1138,7 @@ mod tests {
             env::remove_var("RUSTUP_HOME");
         }
         assert_eq!(
-            get_rustup_home(),
+            get_rustup_home().unwrap(),
             BaseDirs::new().unwrap().home_dir().join(".rustup")
         );
         // RUSTUP_HOME set
@@ -580,6 +1147,6 @@ mod tests {
         unsafe {
             env::set_var("RUSTUP_HOME", rustup_home.to_str().unwrap());
         }
-        assert_eq!(get_rustup_home(), rustup_home);
+        assert_eq!(get_rustup_home().unwrap(), rustup_home);
     }
 }
@@ -0,0 +1,147 @@
+//! Settings of an installation, shared by the tasks that install its components.
+//!
+//! [`install`](crate::toolchain::install) and [`uninstall`](crate::toolchain::uninstall) run with
+//! their own settings, instead of process-wide ones, so several of them can run in the same
+//! process without affecting each other.
+
+use crate::toolchain::{
+    PartialDirs, components_manifest::ComponentsManifest, manifest::ChecksumAlgo,
+};
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    future::Future,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tokio::task::JoinError;
+
+tokio::task_local! {
+    /// Session of the installation run by the current task.
+    static SESSION: Arc<Session>;
+}
+
+/// Settings of an installation or uninstallation.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// Directory the espup directory is created in, `ESPUP_CACHE_DIR` if not provided.
+    pub cache_dir: Option<PathBuf>,
+    /// Algorithm the checksums of the downloads and installed files are computed with.
+    pub checksum_algo: ChecksumAlgo,
+    /// Whether the toolchains are installed for a non-native host, so they can't be executed.
+    pub cross_host: bool,
+    /// Disables the timeouts of the downloads and GitHub queries.
+    pub disable_http_timeouts: bool,
+    /// Size, in bytes, of the buffer the archives are read through while being extracted, the one
+    /// of `BufReader` if not provided.
+    pub extract_buffer_size: Option<u64>,
+    /// Maximum number of threads used to decompress the downloads, all the CPU cores if not
+    /// provided.
+    pub extract_jobs: Option<u32>,
+    /// Maximum combined speed of all the downloads, in bytes per second.
+    pub max_download_speed: Option<u64>,
+    /// Mirrors of the release repositories, as pairs of repository URL and mirror URL.
+    pub mirrors: Vec<(String, String)>,
+    /// Proxy to download through, the one of the environment variables if not provided.
+    pub proxy: Option<String>,
+    /// Queries the latest Xtensa Rust version even if a recent lookup is cached.
+    pub refresh: bool,
+    /// rustup home of espup and the commands it runs, `RUSTUP_HOME` if not provided.
+    pub rustup_home: Option<PathBuf>,
+    /// URLs the latest Xtensa Rust version is queried from when the GitHub API is unreachable.
+    pub version_api_fallbacks: Vec<String>,
+}
+
+/// Settings of a run, along with the state shared by the tasks installing its components.
+#[derive(Default)]
+pub(crate) struct Session {
+    pub(crate) settings: Settings,
+    /// Components manifest the downloads are resolved against.
+    pub(crate) components_manifest: Mutex<Option<ComponentsManifest>>,
+    /// Checksums the assets downloaded from their upstream URL are verified against.
+    pub(crate) expected_checksums: Mutex<HashMap<String, (ChecksumAlgo, String)>>,
+    /// Checksums of the files downloaded during the run, indexed by URL.
+    pub(crate) checksums: Mutex<HashMap<String, String>>,
+    /// Start time and bytes received by all the throttled downloads.
+    pub(crate) download_throttle: Mutex<Option<(Instant, u64)>>,
+    /// `PATH` including the directory of the rustup installed by espup, which the one of this
+    /// process lacks.
+    pub(crate) rustup_path: Mutex<Option<OsString>>,
+    /// Directories being populated, which must be removed if the run is interrupted.
+    pub(crate) partial_dirs: PartialDirs,
+}
+
+/// Runs `future` with the settings, e.g. to query the latest Xtensa Rust version through a proxy.
+pub async fn scope<F: Future>(settings: Settings, future: F) -> F::Output {
+    let session = Session {
+        settings,
+        ..Default::default()
+    };
+    scope_session(Arc::new(session), future).await
+}
+
+/// Runs `future` in a session, e.g. the one of the task spawning it.
+pub(crate) async fn scope_session<F: Future>(session: Arc<Session>, future: F) -> F::Output {
+    SESSION.scope(session, future).await
+}
+
+/// Runs `f` with the settings.
+#[cfg(test)]
+pub(crate) fn sync_scope<R>(settings: Settings, f: impl FnOnce() -> R) -> R {
+    let session = Session {
+        settings,
+        ..Default::default()
+    };
+    SESSION.sync_scope(Arc::new(session), f)
+}
+
+/// Returns the session of the current task, or a default one outside of any.
+pub(crate) fn current() -> Arc<Session> {
+    SESSION.try_with(Arc::clone).unwrap_or_default()
+}
+
+/// Runs `f` on a thread where blocking is acceptable, in the session of the current task.
+pub(crate) async fn spawn_blocking<F, R>(f: F) -> Result<R, JoinError>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let session = current();
+    tokio::task::spawn_blocking(move || SESSION.sync_scope(session, f)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::settings::{Settings, current, scope, spawn_blocking, sync_scope};
+
+    #[tokio::test]
+    async fn test_scope() {
+        let settings = |proxy: &str| Settings {
+            proxy: Some(proxy.to_string()),
+            ..Default::default()
+        };
+        let first = scope(settings("http://first.example.com"), async {
+            tokio::task::yield_now().await;
+            current().settings.proxy.clone()
+        });
+        let second = scope(settings("http://second.example.com"), async {
+            // The settings are kept on the threads of the blocking tasks
+            spawn_blocking(|| current().settings.proxy.clone())
+                .await
+                .unwrap()
+        });
+        // Concurrent runs don't see the settings of each other
+        assert_eq!(
+            tokio::join!(first, second),
+            (
+                Some("http://first.example.com".to_string()),
+                Some("http://second.example.com".to_string())
+            )
+        );
+        assert!(current().settings.proxy.is_none());
+        assert!(sync_scope(settings("http://sync.example.com"), || {
+            current().settings.proxy.is_some()
+        }));
+    }
+}
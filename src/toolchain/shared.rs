@@ -0,0 +1,169 @@
+//! Toolchains installed outside of the rustup home: linked toolchains and shared installations,
+//! installed once into a prefix and used by every user of the machine.
+
+use crate::{
+    error::Error,
+    toolchain::rust::{get_rustup_home, rustup_command},
+};
+use log::{debug, warn};
+use std::{
+    fs::{Metadata, Permissions, read_dir, set_permissions, symlink_metadata},
+    io::{self, ErrorKind},
+    path::Path,
+    process::Stdio,
+};
+
+/// Makes every file and directory under `path` read-only, or writable again for its owner.
+///
+/// Modifying a shared installation owned by another user fails with
+/// [`Error::SharedInstallPermission`].
+pub fn set_read_only(path: &Path, read_only: bool) -> Result<(), Error> {
+    debug!(
+        "Setting '{}' as {}",
+        path.display(),
+        if read_only { "read-only" } else { "writable" }
+    );
+    set_read_only_recursive(path, read_only).map_err(|e| match e.kind() {
+        ErrorKind::PermissionDenied => Error::SharedInstallPermission(path.display().to_string()),
+        _ => e.into(),
+    })
+}
+
+fn set_read_only_recursive(path: &Path, read_only: bool) -> io::Result<()> {
+    let metadata = symlink_metadata(path)?;
+    // Symlinks point inside the installation, their targets are already handled
+    if metadata.file_type().is_symlink() {
+        return Ok(());
+    }
+    // A directory has to be writable before its entries are modified and read-only afterwards
+    if !read_only {
+        set_permissions(path, permissions(&metadata, false))?;
+    }
+    if metadata.is_dir() {
+        for entry in read_dir(path)? {
+            set_read_only_recursive(&entry?.path(), read_only)?;
+        }
+    }
+    if read_only {
+        set_permissions(path, permissions(&metadata, true))?;
+    }
+    Ok(())
+}
+
+fn permissions(metadata: &Metadata, read_only: bool) -> Permissions {
+    let mut permissions = metadata.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = permissions.mode();
+        permissions.set_mode(if read_only {
+            mode & !0o222
+        } else {
+            mode | 0o200
+        });
+    }
+    #[cfg(windows)]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(read_only);
+    permissions
+}
+
+/// Links a toolchain installed outside of the rustup home, so it can be used as `+<name>`.
+pub fn link_toolchain(name: &str, toolchain_dir: &Path) -> Result<(), Error> {
+    debug!(
+        "Linking '{}' as the '{name}' toolchain",
+        toolchain_dir.display()
+    );
+    let output = rustup_command("rustup")
+        .args(["toolchain", "link", name])
+        .arg(toolchain_dir)
+        .stdout(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        warn!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        return Err(Error::LinkToolchain(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Returns whether rustup lists a toolchain, or `None` if rustup can't be run.
+pub fn is_toolchain_registered(name: &str) -> Option<bool> {
+    let output = rustup_command("rustup")
+        .args(["toolchain", "list"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(lists_toolchain(
+        &String::from_utf8_lossy(&output.stdout),
+        name,
+    ))
+}
+
+/// Returns whether the output of `rustup toolchain list` contains a toolchain, whose lines may be
+/// followed by markers like `(active, default)`.
+fn lists_toolchain(toolchains: &str, name: &str) -> bool {
+    toolchains
+        .lines()
+        .any(|line| line.split_whitespace().next() == Some(name))
+}
+
+/// Removes the rustup link of a toolchain installed outside of the rustup home, if any.
+pub fn unlink_toolchain(name: &str) -> Result<(), Error> {
+    // Only links are removed, a toolchain installed in the rustup home is left untouched
+    if !get_rustup_home()?
+        .join("toolchains")
+        .join(name)
+        .is_symlink()
+    {
+        return Ok(());
+    }
+    debug!("Removing the '{name}' toolchain link");
+    let output = rustup_command("rustup")
+        .args(["toolchain", "uninstall", name])
+        .stdout(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        warn!("{}", String::from_utf8_lossy(&output.stderr).trim());
+        return Err(Error::UnlinkToolchain(name.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::shared::{lists_toolchain, set_read_only};
+    use std::fs::{create_dir_all, metadata, remove_dir_all, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_set_read_only() {
+        let temp_dir = TempDir::new().unwrap();
+        let toolchain_dir = temp_dir.path().join("esp");
+        create_dir_all(toolchain_dir.join("bin")).unwrap();
+        let rustc = toolchain_dir.join("bin").join("rustc");
+        write(&rustc, "").unwrap();
+
+        set_read_only(&toolchain_dir, true).unwrap();
+        assert!(metadata(&rustc).unwrap().permissions().readonly());
+        assert!(metadata(&toolchain_dir).unwrap().permissions().readonly());
+
+        set_read_only(&toolchain_dir, false).unwrap();
+        assert!(!metadata(&rustc).unwrap().permissions().readonly());
+        remove_dir_all(&toolchain_dir).unwrap();
+    }
+
+    #[test]
+    fn test_lists_toolchain() {
+        let toolchains = "stable-x86_64-unknown-linux-gnu (active, default)\nesp\nesp-old\n";
+        assert!(lists_toolchain(toolchains, "esp"));
+        assert!(lists_toolchain(
+            toolchains,
+            "stable-x86_64-unknown-linux-gnu"
+        ));
+        assert!(!lists_toolchain(toolchains, "es"));
+        assert!(!lists_toolchain("no installed toolchains\n", "esp"));
+    }
+}
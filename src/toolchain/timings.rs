@@ -0,0 +1,150 @@
+//! Time spent installing each component, split into the download and extraction phases.
+
+use serde_json::{Value, json};
+use std::{cmp::Reverse, future::Future, sync::Mutex, time::Duration};
+
+tokio::task_local! {
+    /// Name of the component installed by the current task.
+    static COMPONENT: String;
+}
+
+/// Phases recorded for each component, the download and extraction ones are recorded by
+/// `download_file` for the component installed by the current task.
+static PHASES: Mutex<Vec<(String, Phase, Duration)>> = Mutex::new(Vec::new());
+
+/// Phase of the installation of a component.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Phase {
+    /// Downloading, and verifying, its archives.
+    Download,
+    /// Extracting its archives.
+    Extract,
+    /// The whole installation, including its retries.
+    Total,
+}
+
+/// Time spent installing a component.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComponentTimings {
+    /// Name of the component.
+    pub name: String,
+    /// Time spent downloading its archives.
+    pub download: Duration,
+    /// Time spent extracting its archives.
+    pub extract: Duration,
+    /// Time spent installing it.
+    pub total: Duration,
+}
+
+impl ComponentTimings {
+    /// Time spent neither downloading nor extracting, e.g. running its installer.
+    pub fn other(&self) -> Duration {
+        self.total.saturating_sub(self.download + self.extract)
+    }
+}
+
+/// Runs the installation of `component`, so the phases recorded while it runs are attributed to it.
+pub(crate) async fn scope<F: Future>(component: String, future: F) -> F::Output {
+    COMPONENT.scope(component, future).await
+}
+
+/// Records the time spent in a phase of the component installed by the current task.
+pub(crate) fn record(phase: Phase, duration: Duration) {
+    if let Ok(component) = COMPONENT.try_with(Clone::clone) {
+        record_for(component, phase, duration);
+    }
+}
+
+/// Records the time spent in a phase of `component`.
+pub(crate) fn record_for(component: String, phase: Phase, duration: Duration) {
+    PHASES.lock().unwrap().push((component, phase, duration));
+}
+
+/// Returns the time spent installing each of the `components`, forgetting their recorded phases.
+pub(crate) fn take(components: &[String]) -> Vec<ComponentTimings> {
+    let mut phases = PHASES.lock().unwrap();
+    let timings = components
+        .iter()
+        .map(|name| {
+            let mut timings = ComponentTimings {
+                name: name.clone(),
+                ..Default::default()
+            };
+            for (_, phase, duration) in phases.iter().filter(|(component, ..)| component == name) {
+                match phase {
+                    Phase::Download => timings.download += *duration,
+                    Phase::Extract => timings.extract += *duration,
+                    Phase::Total => timings.total += *duration,
+                }
+            }
+            timings
+        })
+        .collect();
+    phases.retain(|(component, ..)| !components.contains(component));
+    timings
+}
+
+/// Formats the time spent installing each component, slowest first.
+pub fn breakdown(timings: &[ComponentTimings], elapsed: Duration) -> String {
+    let mut timings = timings.to_vec();
+    timings.sort_by_key(|timing| Reverse(timing.total));
+    let mut breakdown = format!("Installation took {:.1}s", elapsed.as_secs_f64());
+    for timing in timings {
+        breakdown.push_str(&format!(
+            "\n  {}: {:.1}s (download: {:.1}s, extract: {:.1}s, other: {:.1}s)",
+            timing.name,
+            timing.total.as_secs_f64(),
+            timing.download.as_secs_f64(),
+            timing.extract.as_secs_f64(),
+            timing.other().as_secs_f64()
+        ));
+    }
+    breakdown
+}
+
+/// Returns the time spent installing each component as JSON, in seconds.
+pub fn to_json(timings: &[ComponentTimings], elapsed: Duration) -> Value {
+    json!({
+        "total": elapsed.as_secs_f64(),
+        "components": timings
+            .iter()
+            .map(|timing| json!({
+                "name": timing.name,
+                "total": timing.total.as_secs_f64(),
+                "download": timing.download.as_secs_f64(),
+                "extract": timing.extract.as_secs_f64(),
+                "other": timing.other().as_secs_f64(),
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::toolchain::timings::{Phase, breakdown, record, record_for, scope, take};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_timings() {
+        let name = "Timings test".to_string();
+        scope(name.clone(), async {
+            record(Phase::Download, Duration::from_secs(3));
+            record(Phase::Extract, Duration::from_secs(1));
+            record(Phase::Download, Duration::from_secs(2));
+        })
+        .await;
+        // Outside of a component installation nothing is recorded
+        record(Phase::Download, Duration::from_secs(60));
+        record_for(name.clone(), Phase::Total, Duration::from_secs(10));
+
+        let timings = take(std::slice::from_ref(&name));
+        assert_eq!(timings[0].download, Duration::from_secs(5));
+        assert_eq!(timings[0].extract, Duration::from_secs(1));
+        assert_eq!(timings[0].other(), Duration::from_secs(4));
+        assert_eq!(
+            breakdown(&timings, Duration::from_secs(12)),
+            "Installation took 12.0s\n  Timings test: 10.0s (download: 5.0s, extract: 1.0s, other: 4.0s)"
+        );
+        assert_eq!(take(&[name])[0].total, Duration::ZERO);
+    }
+}
@@ -0,0 +1,217 @@
+//! Visual Studio Code settings for rust-analyzer.
+
+use crate::{
+    env::{PATH_SEPARATOR, parse_export},
+    error::Error,
+    targets::Target,
+};
+use log::{debug, info, warn};
+use serde_json::{Map, Value, json};
+use std::{
+    collections::HashSet,
+    fs::{copy, create_dir_all, read_to_string, write},
+    path::{Path, PathBuf},
+};
+
+/// Writes, or merges into an existing one, the `.vscode/settings.json` file of `dir` so
+/// rust-analyzer uses the installed toolchains.
+///
+/// The original settings file, if any, is backed up as `settings.json.bak`. Its comments and
+/// trailing commas, which VS Code allows, are accepted but not preserved.
+///
+/// `RUSTUP_TOOLCHAIN` is only set when a `toolchain` is given.
+pub fn write_settings(
+    dir: &Path,
+    exports: &[String],
+    toolchain: Option<&str>,
+    targets: &HashSet<Target>,
+    std: bool,
+) -> Result<PathBuf, Error> {
+    let vscode_dir = dir.join(".vscode");
+    let settings_path = vscode_dir.join("settings.json");
+
+    let mut settings = if settings_path.exists() {
+        let contents = read_to_string(&settings_path)?;
+        let settings: Value = match serde_json::from_str(&contents) {
+            Ok(settings) => settings,
+            Err(_) => {
+                let settings = serde_json::from_str(&strip_jsonc(&contents)).map_err(|_| {
+                    Error::InvalidVscodeSettings(settings_path.display().to_string())
+                })?;
+                warn!(
+                    "The comments of '{}' are not preserved, the original settings are backed up",
+                    settings_path.display()
+                );
+                settings
+            }
+        };
+        let backup_path = vscode_dir.join("settings.json.bak");
+        debug!("Backing up '{}'", settings_path.display());
+        copy(&settings_path, backup_path)?;
+        settings
+    } else {
+        create_dir_all(&vscode_dir)
+            .map_err(|_| Error::CreateDirectory(vscode_dir.display().to_string()))?;
+        json!({})
+    };
+    let settings = settings
+        .as_object_mut()
+        .ok_or_else(|| Error::InvalidVscodeSettings(settings_path.display().to_string()))?;
+
+    let mut paths = Vec::new();
+    let mut extra_env = Map::new();
+    for (name, value) in exports.iter().filter_map(|e| parse_export(e)) {
+        if name == "PATH" {
+            paths.push(value);
+        } else {
+            extra_env.insert(name, Value::String(value));
+        }
+    }
+    if !paths.is_empty() {
+        paths.push("${env:PATH}".to_string());
+        extra_env.insert(
+            "PATH".to_string(),
+            Value::String(paths.join(PATH_SEPARATOR)),
+        );
+    }
+    if let Some(toolchain) = toolchain {
+        extra_env.insert(
+            "RUSTUP_TOOLCHAIN".to_string(),
+            Value::String(toolchain.to_string()),
+        );
+    }
+
+    let server_env = settings
+        .entry("rust-analyzer.server.extraEnv")
+        .or_insert_with(|| json!({}));
+    match server_env.as_object_mut() {
+        Some(server_env) => server_env.extend(extra_env),
+        None => *server_env = Value::Object(extra_env),
+    }
+    settings.insert("rust-analyzer.check.allTargets".to_string(), json!(false));
+    // A target can only be configured when there is a single one
+    if targets.len() == 1 {
+        let target = targets.iter().next().unwrap();
+        settings.insert(
+            "rust-analyzer.cargo.target".to_string(),
+            json!(target.triple(std)),
+        );
+    }
+
+    let contents = serde_json::to_string_pretty(settings).map_err(|_| Error::SerializeJson)?;
+    write(&settings_path, contents)?;
+    info!("VS Code settings written to '{}'", settings_path.display());
+
+    Ok(settings_path)
+}
+
+/// Removes the comments and trailing commas of a JSONC document, the format of the VS Code
+/// settings, so it can be parsed as JSON.
+fn strip_jsonc(contents: &str) -> String {
+    let mut stripped = String::with_capacity(contents.len());
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                stripped.push(c);
+                while let Some(c) = chars.next() {
+                    stripped.push(c);
+                    match c {
+                        '\\' => stripped.extend(chars.next()),
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'/') => while chars.next_if(|&c| c != '\n').is_some() {},
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = None;
+                for c in chars.by_ref() {
+                    if previous == Some('*') && c == '/' {
+                        break;
+                    }
+                    previous = Some(c);
+                }
+            }
+            '}' | ']' => {
+                // Drops the trailing comma of the object or array
+                let trimmed = stripped.trim_end().len();
+                if stripped[..trimmed].ends_with(',') {
+                    stripped.remove(trimmed - 1);
+                }
+                stripped.push(c);
+            }
+            _ => stripped.push(c),
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        targets::Target,
+        vscode::{strip_jsonc, write_settings},
+    };
+    use serde_json::Value;
+    use std::fs::{create_dir_all, read_to_string, write};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_write_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let vscode_dir = temp_dir.path().join(".vscode");
+        create_dir_all(&vscode_dir).unwrap();
+        let original =
+            r#"{"editor.formatOnSave": true, "rust-analyzer.server.extraEnv": {"FOO": "bar"}}"#;
+        write(vscode_dir.join("settings.json"), original).unwrap();
+
+        let exports = vec!["export LIBCLANG_PATH=\"/esp/lib\"".to_string()];
+        let targets = [Target::ESP32S3].into_iter().collect();
+        let settings_path =
+            write_settings(temp_dir.path(), &exports, Some("esp"), &targets, false).unwrap();
+
+        let settings: Value =
+            serde_json::from_str(&read_to_string(settings_path).unwrap()).unwrap();
+        assert_eq!(settings["editor.formatOnSave"], true);
+        let extra_env = &settings["rust-analyzer.server.extraEnv"];
+        assert_eq!(extra_env["FOO"], "bar");
+        assert_eq!(extra_env["LIBCLANG_PATH"], "/esp/lib");
+        assert_eq!(extra_env["RUSTUP_TOOLCHAIN"], "esp");
+        assert_eq!(
+            settings["rust-analyzer.cargo.target"],
+            "xtensa-esp32s3-none-elf"
+        );
+        assert_eq!(
+            read_to_string(vscode_dir.join("settings.json.bak")).unwrap(),
+            original
+        );
+
+        // VS Code settings may have comments and trailing commas
+        write(
+            vscode_dir.join("settings.json"),
+            "{\n    // Format\n    \"editor.formatOnSave\": true, /* \"a\": 1, */\n}\n",
+        )
+        .unwrap();
+        let settings_path =
+            write_settings(temp_dir.path(), &exports, None, &targets, false).unwrap();
+        let settings: Value =
+            serde_json::from_str(&read_to_string(settings_path).unwrap()).unwrap();
+        assert_eq!(settings["editor.formatOnSave"], true);
+        assert!(settings.get("a").is_none());
+        assert!(
+            settings["rust-analyzer.server.extraEnv"]
+                .get("RUSTUP_TOOLCHAIN")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_strip_jsonc() {
+        assert_eq!(
+            strip_jsonc("{\"a\": [1, 2,], // b\n \"c\": \"//d, /*e*/\",\n}"),
+            "{\"a\": [1, 2], \n \"c\": \"//d, /*e*/\"\n}"
+        );
+    }
+}
@@ -13,6 +13,14 @@ fn verify_help() {
         .success();
 }
 
+#[test]
+fn verify_cache_key_help() {
+    assert_cmd::Command::new(assert_cmd::cargo_bin!("espup"))
+        .args(["cache-key", "--help"])
+        .assert()
+        .success();
+}
+
 #[test]
 fn verify_install_help() {
     assert_cmd::Command::new(assert_cmd::cargo_bin!("espup"))
@@ -36,3 +44,11 @@ fn verify_uninstall_help() {
         .assert()
         .success();
 }
+
+#[test]
+fn verify_verify_help() {
+    assert_cmd::Command::new(assert_cmd::cargo_bin!("espup"))
+        .args(["verify", "--help"])
+        .assert()
+        .success();
+}